@@ -1,6 +1,8 @@
+use std::ascii::AsciiExt;
 use std::cmp::Ordering;
 use std::cmp::Ordering::*;
 use std::fmt;
+use std::isize;
 use std::ops::{Add, Sub, Mul, Div, Rem, BitAnd, BitOr, Shl, Shr};
 
 pub enum RusqlStatement {
@@ -11,17 +13,39 @@ pub enum RusqlStatement {
     Insert(InsertDef),
     Select(SelectDef),
     Update(UpdateDef),
+    With((WithClause, Box<RusqlStatement>)),
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Show, Clone, PartialEq, Eq)]
 pub enum ColumnType {
     Integer,
+    Real,
     Text,
+    Numeric,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+impl ColumnType {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ColumnType::Integer => "INTEGER",
+            ColumnType::Real => "REAL",
+            ColumnType::Text => "TEXT",
+            ColumnType::Numeric => "NUMERIC",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
 pub enum ColumnConstraint {
     PrimaryKey,
+    AutoIncrement,
+    Generated((Expression, GeneratedStorage)),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum GeneratedStorage {
+    Virtual,
+    Stored,
 }
 
 #[derive(Show, Clone, PartialEq)]
@@ -35,24 +59,64 @@ pub enum LiteralValue {
 
 impl LiteralValue {
     pub fn to_uint(&self) -> usize {
-        match self {
-            &LiteralValue::Integer(i) => i as usize,
-            _ => 0, // FIXME ???
-        }
+        self.to_int() as usize
     }
+
     pub fn to_int(&self) -> isize {
-        match self {
-            &LiteralValue::Integer(i) => i,
-            &LiteralValue::Boolean(b) => if b { 1 } else { 0 },
-            _ => 0, // FIXME ???
+        match self.cast(ColumnType::Integer) {
+            LiteralValue::Integer(i) => i,
+            _ => 0,
         }
     }
 
+    // NULL and text that doesn't start with a number are false.
     pub fn to_bool(&self) -> bool {
-        match self {
-            &LiteralValue::Integer(i) => i != 0,
-            &LiteralValue::Boolean(b) => b,
-            _ => false, // FIXME ???
+        self.to_real() != 0.0
+    }
+
+    pub fn to_real(&self) -> f64 {
+        match self.cast(ColumnType::Real) {
+            LiteralValue::Real(r) => r,
+            _ => 0.0,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        match self.cast(ColumnType::Text) {
+            LiteralValue::Text(t) => t,
+            _ => String::new(),
+        }
+    }
+
+    // Text becomes a number by its longest prefix that reads as one, or 0.
+    pub fn cast(&self, column_type: ColumnType) -> LiteralValue {
+        // https://www.sqlite.org/lang_expr.html#castexpr
+        match (self, column_type) {
+            (&LiteralValue::Null, _) => LiteralValue::Null,
+            (&LiteralValue::Integer(i), ColumnType::Integer) => LiteralValue::Integer(i),
+            (&LiteralValue::Real(r), ColumnType::Integer) => LiteralValue::Integer(real_to_int(r)),
+            (&LiteralValue::Boolean(b), ColumnType::Integer) => LiteralValue::Integer(if b { 1 } else { 0 }),
+            (&LiteralValue::Text(ref t), ColumnType::Integer) => LiteralValue::Integer(integer_prefix(t.as_slice())),
+            (&LiteralValue::Integer(i), ColumnType::Real) => LiteralValue::Real(i as f64),
+            (&LiteralValue::Real(r), ColumnType::Real) => LiteralValue::Real(r),
+            (&LiteralValue::Boolean(b), ColumnType::Real) => LiteralValue::Real(if b { 1.0 } else { 0.0 }),
+            (&LiteralValue::Text(ref t), ColumnType::Real) => {
+                LiteralValue::Real(real_prefix(t.as_slice()).map_or(0.0, |(r, _)| r))
+            }
+            (&LiteralValue::Text(ref t), ColumnType::Text) => LiteralValue::Text(t.clone()),
+            (&LiteralValue::Boolean(b), ColumnType::Text) => LiteralValue::Text(if b { "1" } else { "0" }.to_string()),
+            (value, ColumnType::Text) => LiteralValue::Text(format!("{}", value)),
+            // NUMERIC keeps a real that has no fractional part as an integer.
+            (&LiteralValue::Text(ref t), ColumnType::Numeric) => match real_prefix(t.as_slice()) {
+                Some((r, true)) => LiteralValue::Real(r).cast(ColumnType::Numeric),
+                Some(_) => LiteralValue::Integer(integer_prefix(t.as_slice())),
+                None => LiteralValue::Integer(0),
+            },
+            (&LiteralValue::Real(r), ColumnType::Numeric) if r.fract() == 0.0 && r == real_to_int(r) as f64 => {
+                LiteralValue::Integer(r as isize)
+            }
+            (&LiteralValue::Real(r), ColumnType::Numeric) => LiteralValue::Real(r),
+            (value, ColumnType::Numeric) => value.cast(ColumnType::Integer),
         }
     }
 
@@ -64,7 +128,14 @@ impl LiteralValue {
             return x.cmp(&y)
         }
 
-        Equal
+        if self.is_numeric() && other.is_numeric() {
+            return self.to_real().partial_cmp(&other.to_real()).unwrap_or(Equal);
+        }
+
+        match (self, other) {
+            (&LiteralValue::Text(ref x), &LiteralValue::Text(ref y)) => x.cmp(y),
+            _ => Equal,
+        }
     }
 
     pub fn lt(&self, other: &Self) -> LiteralValue {
@@ -106,6 +177,30 @@ impl LiteralValue {
         }
     }
 
+    pub fn is_numeric(&self) -> bool {
+        match self {
+            &LiteralValue::Integer(..) | &LiteralValue::Real(..) | &LiteralValue::Boolean(..) => true,
+            _ => false,
+        }
+    }
+
+    fn type_rank(&self) -> usize {
+        match self {
+            &LiteralValue::Null => 0,
+            &LiteralValue::Integer(..) | &LiteralValue::Real(..) | &LiteralValue::Boolean(..) => 1,
+            &LiteralValue::Text(..) => 2,
+        }
+    }
+
+    // NULLs first, then numbers, then text, as SQLite sorts. Unlike `cmp`, values
+    // of different types are ordered.
+    pub fn sort_cmp(&self, other: &Self) -> Ordering {
+        match self.type_rank().cmp(&other.type_rank()) {
+            Equal => self.cmp(other),
+            ordering => ordering,
+        }
+    }
+
     pub fn neg(&self) -> LiteralValue {
         match self {
             &LiteralValue::Integer(i) => LiteralValue::Integer(-i),
@@ -149,6 +244,104 @@ impl LiteralValue {
     }
 }
 
+fn real_to_int(r: f64) -> isize {
+    if r != r {
+        0
+    } else if r >= isize::MAX as f64 {
+        isize::MAX
+    } else if r <= isize::MIN as f64 {
+        isize::MIN
+    } else {
+        r as isize
+    }
+}
+
+fn integer_prefix(s: &str) -> isize {
+    let s = s.trim_left();
+    let (negative, digits) = match s.chars().next() {
+        Some('-') => (true, &s[1..]),
+        Some('+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+
+    let mut n: isize = 0;
+    for c in digits.chars() {
+        let digit = match c.to_digit(10) {
+            Some(digit) => digit as isize,
+            None => break,
+        };
+
+        n = match n.checked_mul(10).and_then(|n| if negative { n.checked_sub(digit) } else { n.checked_add(digit) }) {
+            Some(n) => n,
+            None if negative => return isize::MIN,
+            None => return isize::MAX,
+        };
+    }
+
+    n
+}
+
+fn real_prefix(s: &str) -> Option<(f64, bool)> {
+    let chars: Vec<char> = s.trim_left().chars().collect();
+    let is_digit = |i: usize| i < chars.len() && chars[i].is_digit(10);
+
+    let mut number = String::new();
+    let mut i = 0us;
+    let mut real = false;
+
+    if i < chars.len() && (chars[i] == '-' || chars[i] == '+') {
+        number.push(chars[i]);
+        i += 1;
+    }
+
+    let start = i;
+    while is_digit(i) {
+        number.push(chars[i]);
+        i += 1;
+    }
+    let mut has_digits = i > start;
+
+    if i < chars.len() && chars[i] == '.' {
+        number.push_str(if has_digits { "." } else { "0." });
+        i += 1;
+
+        let start = i;
+        while is_digit(i) {
+            number.push(chars[i]);
+            i += 1;
+        }
+        if i == start {
+            number.push('0');
+        } else {
+            has_digits = true;
+        }
+        real = true;
+    }
+
+    if !has_digits {
+        return None;
+    }
+
+    // An exponent only counts if it has digits.
+    if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+        let mut j = i + 1;
+        if j < chars.len() && (chars[j] == '-' || chars[j] == '+') {
+            j += 1;
+        }
+
+        if is_digit(j) {
+            number.extend(chars[i..j].iter().map(|c| *c));
+            while is_digit(j) {
+                number.push(chars[j]);
+                j += 1;
+            }
+            real = true;
+        }
+    }
+
+    number.as_slice().parse::<f64>().map(|r| (r, real))
+}
+
 impl fmt::String for LiteralValue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -263,8 +456,11 @@ pub struct TableDef {
     pub if_not_exists: bool,
 }
 
+pub type ResultExpression = (Expression, Option<String>);
+
+#[derive(Show, Clone, PartialEq)]
 pub enum ResultColumn {
-    Expressions(Vec<Expression>),
+    Expressions(Vec<ResultExpression>),
     Asterisk,
 }
 
@@ -272,10 +468,11 @@ pub struct InsertDef {
     pub table_name: String,
     pub column_names: Option<Vec<String>>,
     pub data_source: InsertDataSource,
+    pub returning: Option<ResultColumn>,
 }
 
 pub enum InsertDataSource {
-    Values(Vec<Vec<LiteralValue>>),
+    Values(Vec<Vec<Expression>>),
     Select(SelectDef),
     DefaultValues,
     Error,
@@ -288,11 +485,105 @@ pub struct ColumnDef {
     pub column_constraints: Vec<ColumnConstraint>,
 }
 
+impl ColumnDef {
+    pub fn generated(&self) -> Option<(&Expression, GeneratedStorage)> {
+        for constraint in self.column_constraints.iter() {
+            if let &ColumnConstraint::Generated((ref expr, storage)) = constraint {
+                return Some((expr, storage));
+            }
+        }
+        None
+    }
+
+    pub fn is_generated(&self) -> bool {
+        self.generated().is_some()
+    }
+}
+
+#[derive(Show, Clone, PartialEq)]
 pub struct SelectDef {
+    pub distinct: bool,
     pub result_column: ResultColumn,
     pub from_clause: Option<FromClause>,
     pub where_expr: Option<Expression>,
+    pub group_by: Option<Vec<Expression>>,
+    pub having: Option<Expression>,
+    pub windows: Vec<NamedWindow>,
+    // The ORDER BY and LIMIT apply to the whole compound.
+    pub compound: Vec<CompoundSelect>,
     pub ordering_terms: Option<Vec<OrderingTerm>>,
+    pub limit: Option<Expression>,
+    pub offset: Option<Expression>,
+}
+
+pub type CompoundSelect = (CompoundOperator, SelectDef);
+
+pub type NamedWindow = (String, WindowDef);
+
+#[derive(Show, Clone, PartialEq)]
+pub enum Over {
+    Window(WindowDef),
+    Name(String),
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct WindowDef {
+    pub base: Option<String>,
+    pub partition_by: Option<Vec<Expression>>,
+    pub ordering_terms: Option<Vec<OrderingTerm>>,
+    pub frame: Option<FrameSpec>,
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct FrameSpec {
+    pub units: FrameUnits,
+    pub start: FrameBound,
+    pub end: FrameBound,
+}
+
+#[derive(Copy, Show, Clone, PartialEq)]
+pub enum FrameUnits {
+    Rows,
+    Range,
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub enum FrameBound {
+    UnboundedPreceding,
+    Preceding(Expression),
+    CurrentRow,
+    Following(Expression),
+    UnboundedFollowing,
+}
+
+pub struct WithClause {
+    pub recursive: bool,
+    pub ctes: Vec<CommonTableExpression>,
+}
+
+pub struct CommonTableExpression {
+    pub name: String,
+    pub column_names: Option<Vec<String>>,
+    pub select_def: SelectDef,
+}
+
+#[derive(Copy, Show, Clone, PartialEq)]
+pub enum CompoundOperator {
+    Union,
+    UnionAll,
+    Intersect,
+    Except,
+}
+
+impl fmt::String for CompoundOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            CompoundOperator::Union => "UNION",
+            CompoundOperator::UnionAll => "UNION ALL",
+            CompoundOperator::Intersect => "INTERSECT",
+            CompoundOperator::Except => "EXCEPT",
+        })
+    }
 }
 
 pub struct DropTableDef {
@@ -309,21 +600,277 @@ pub struct AlterTableDef {
     pub mode: AlterTable,
 }
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, PartialEq)]
 pub enum Expression {
     LiteralValue(LiteralValue),
     TableName((String, Box<Expression>)),
     ColumnName(String),
     BinaryOperator((BinaryOperator, Box<Expression>, Box<Expression>)),
     UnaryOperator((UnaryOperator, Box<Expression>)),
+    Function(FunctionCall),
+    Cast((Box<Expression>, ColumnType)),
+    WindowFunction((FunctionCall, Over)),
+    Case((Option<Box<Expression>>, Vec<CaseBranch>, Option<Box<Expression>>)),
+    Subquery(Box<SelectDef>),
+    Exists(Box<SelectDef>),
+    // Set for NOT IN.
+    InSelect((Box<Expression>, Box<SelectDef>, bool)),
+    List(Vec<Expression>),
     Null,
 }
 
+pub type CaseBranch = (Expression, Expression);
+
+pub type Operation = (BinaryOperator, Expression);
+
 impl Expression {
-    pub fn unwrap_binary_operator(&self) -> (BinaryOperator, Expression, Expression) {
+    // Operators of the same precedence group from the left.
+    pub fn from_chain(first: Expression, operations: Vec<Operation>) -> Expression {
+        let mut operands: Vec<Expression> = vec![first];
+        let mut operators: Vec<BinaryOperator> = Vec::new();
+
+        for (operator, operand) in operations.into_iter() {
+            while operators.last().map_or(false, |last| last.ord_val() <= operator.ord_val()) {
+                Expression::reduce_chain(&mut operands, &mut operators);
+            }
+            operators.push(operator);
+            operands.push(operand);
+        }
+
+        while operators.len() > 0 {
+            Expression::reduce_chain(&mut operands, &mut operators);
+        }
+
+        operands.pop().unwrap()
+    }
+
+    fn reduce_chain(operands: &mut Vec<Expression>, operators: &mut Vec<BinaryOperator>) {
+        let operator = operators.pop().unwrap();
+        let right = operands.pop().unwrap();
+        let left = operands.pop().unwrap();
+
+        operands.push(match (operator, right) {
+            (BinaryOperator::In, Expression::Subquery(select_def)) => {
+                Expression::InSelect((box left, select_def, false))
+            }
+            (BinaryOperator::NotIn, Expression::Subquery(select_def)) => {
+                Expression::InSelect((box left, select_def, true))
+            }
+            (operator, right) => Expression::BinaryOperator((operator, box left, box right)),
+        });
+    }
+
+    pub fn contains_aggregate(&self) -> bool {
+        self.aggregate_call().is_some()
+    }
+
+    pub fn aggregate_call(&self) -> Option<&FunctionCall> {
+        match self {
+            &Expression::Function(ref call) if call.is_aggregate() => Some(call),
+            &Expression::Function(ref call) => call.args.iter().filter_map(|arg| arg.aggregate_call()).next(),
+            &Expression::TableName((_, ref expr)) => expr.aggregate_call(),
+            &Expression::BinaryOperator((_, ref left, ref right)) => {
+                left.aggregate_call().or_else(|| right.aggregate_call())
+            }
+            &Expression::UnaryOperator((_, ref expr)) => expr.aggregate_call(),
+            &Expression::Cast((ref expr, _)) => expr.aggregate_call(),
+            // Aggregates within a subquery belong to the subquery.
+            &Expression::InSelect((ref expr, _, _)) => expr.aggregate_call(),
+            &Expression::List(ref exprs) => exprs.iter().filter_map(|expr| expr.aggregate_call()).next(),
+            &Expression::Case((ref base, ref branches, ref else_expr)) => {
+                base.iter().chain(else_expr.iter()).filter_map(|expr| expr.aggregate_call()).next().or_else(|| {
+                    branches.iter().filter_map(|&(ref when, ref then)| {
+                        when.aggregate_call().or_else(|| then.aggregate_call())
+                    }).next()
+                })
+            }
+            // An aggregate with an OVER clause is computed per row, not per group.
+            &Expression::WindowFunction(_) => None,
+            _ => None,
+        }
+    }
+
+    // The column references in the expression, outside of subqueries.
+    pub fn column_refs<'a>(&'a self, refs: &mut Vec<&'a Expression>) {
         match self {
-            &Expression::BinaryOperator((b, ref left, ref right)) => (b, *left.clone(), *right.clone()),
-            _ => (BinaryOperator::Null, Expression::Null, Expression::Null),
+            &Expression::ColumnName(_) | &Expression::TableName(_) => refs.push(self),
+            &Expression::Function(ref call) | &Expression::WindowFunction((ref call, _)) => {
+                for arg in call.args.iter() {
+                    arg.column_refs(refs);
+                }
+            }
+            &Expression::BinaryOperator((_, ref left, ref right)) => {
+                left.column_refs(refs);
+                right.column_refs(refs);
+            }
+            &Expression::UnaryOperator((_, ref expr)) | &Expression::Cast((ref expr, _))
+                | &Expression::InSelect((ref expr, _, _)) => expr.column_refs(refs),
+            &Expression::Case((ref base, ref branches, ref else_expr)) => {
+                for expr in base.iter().chain(else_expr.iter()) {
+                    expr.column_refs(refs);
+                }
+                for &(ref when, ref then) in branches.iter() {
+                    when.column_refs(refs);
+                    then.column_refs(refs);
+                }
+            }
+            &Expression::List(ref exprs) => {
+                for expr in exprs.iter() {
+                    expr.column_refs(refs);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl fmt::String for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Expression::LiteralValue(LiteralValue::Text(ref t)) => write!(f, "'{}'", t),
+            &Expression::LiteralValue(ref value) => write!(f, "{}", value),
+            &Expression::TableName((ref name, ref expr)) => write!(f, "{}.{}", name, expr),
+            &Expression::ColumnName(ref name) => write!(f, "{}", name),
+            &Expression::BinaryOperator((b, ref left, ref right)) => {
+                try!(fmt_operand(f, &**left, b, false));
+                try!(write!(f, " {} ", b.symbol()));
+                fmt_operand(f, &**right, b, true)
+            }
+            &Expression::UnaryOperator((u, ref expr)) => match **expr {
+                Expression::BinaryOperator(_) => write!(f, "{}({})", u.symbol(), expr),
+                _ => write!(f, "{}{}", u.symbol(), expr),
+            },
+            &Expression::Function(ref call) => write!(f, "{}", call),
+            &Expression::Cast((ref expr, column_type)) => write!(f, "CAST({} AS {})", expr, column_type.name()),
+            &Expression::WindowFunction((ref call, Over::Name(ref name))) => write!(f, "{} OVER {}", call, name),
+            &Expression::WindowFunction((ref call, Over::Window(_))) => write!(f, "{} OVER (...)", call),
+            &Expression::Case((ref base, ref branches, ref else_expr)) => {
+                try!(write!(f, "CASE"));
+                if let &Some(ref base) = base {
+                    try!(write!(f, " {}", base));
+                }
+                for &(ref when, ref then) in branches.iter() {
+                    try!(write!(f, " WHEN {} THEN {}", when, then));
+                }
+                if let &Some(ref else_expr) = else_expr {
+                    try!(write!(f, " ELSE {}", else_expr));
+                }
+                write!(f, " END")
+            }
+            &Expression::Subquery(_) => write!(f, "(SELECT ...)"),
+            &Expression::Exists(_) => write!(f, "EXISTS (SELECT ...)"),
+            &Expression::InSelect((ref expr, _, negated)) => {
+                write!(f, "{} {}IN (SELECT ...)", expr, if negated { "NOT " } else { "" })
+            }
+            &Expression::List(ref exprs) => {
+                try!(write!(f, "("));
+                for (i, expr) in exprs.iter().enumerate() {
+                    try!(write!(f, "{}{}", if i > 0 { ", " } else { "" }, expr));
+                }
+                write!(f, ")")
+            }
+            &Expression::Null => write!(f, "NULL"),
+        }
+    }
+}
+
+// Parenthesizes an operand that would otherwise bind to the operators around it
+// when parsed back.
+fn fmt_operand(f: &mut fmt::Formatter, expr: &Expression, parent: BinaryOperator, right: bool) -> fmt::Result {
+    let parenthesize = match *expr {
+        Expression::BinaryOperator((child, _, _)) => match (parent, child) {
+            // The bounds of a BETWEEN and the ESCAPE of a LIKE are parsed as operands.
+            (BinaryOperator::Between, BinaryOperator::And) | (BinaryOperator::NotBetween, BinaryOperator::And)
+                | (BinaryOperator::Like, BinaryOperator::Escape)
+                | (BinaryOperator::NotLike, BinaryOperator::Escape) if right => false,
+            _ => child.ord_val() > parent.ord_val() || (right && child.ord_val() == parent.ord_val()),
+        },
+        _ => false,
+    };
+
+    if parenthesize {
+        write!(f, "({})", expr)
+    } else {
+        write!(f, "{}", expr)
+    }
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct FunctionCall {
+    pub name: String,
+    pub distinct: bool,
+    // Empty for COUNT(*).
+    pub args: Vec<Expression>,
+}
+
+impl fmt::String for FunctionCall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}(", self.name));
+        if self.distinct {
+            try!(write!(f, "DISTINCT "));
+        }
+        if self.args.len() == 0 && self.is_aggregate() {
+            try!(write!(f, "*"));
+        }
+        for (i, arg) in self.args.iter().enumerate() {
+            try!(write!(f, "{}{}", if i > 0 { ", " } else { "" }, arg));
+        }
+        write!(f, ")")
+    }
+}
+
+impl FunctionCall {
+    pub fn lower_name(&self) -> String {
+        self.name.as_slice().to_ascii_lowercase()
+    }
+
+    pub fn is_aggregate(&self) -> bool {
+        match (self.lower_name().as_slice(), self.args.len()) {
+            ("count", 0) | ("count", 1) => true,
+            ("sum", 1) | ("total", 1) | ("avg", 1) => true,
+            // With more than one argument, min() and max() are scalar functions.
+            ("min", 1) | ("max", 1) => true,
+            ("group_concat", 1) | ("group_concat", 2) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_window_function(&self) -> bool {
+        match (self.lower_name().as_slice(), self.args.len()) {
+            ("row_number", 0) | ("rank", 0) | ("dense_rank", 0) | ("ntile", 1) => true,
+            ("lag", 1) | ("lag", 2) | ("lag", 3) => true,
+            ("lead", 1) | ("lead", 2) | ("lead", 3) => true,
+            ("first_value", 1) | ("last_value", 1) => true,
+            _ => self.is_aggregate(),
+        }
+    }
+
+    fn arity(&self) -> Option<(usize, Option<usize>)> {
+        match self.lower_name().as_slice() {
+            "random" | "last_insert_rowid" | "changes" => Some((0, Some(0))),
+            "count" => Some((0, Some(1))),
+            "sum" | "total" | "avg" => Some((1, Some(1))),
+            "group_concat" => Some((1, Some(2))),
+            "abs" | "length" | "lower" | "upper" | "hex" | "quote" => Some((1, Some(1))),
+            "trim" | "ltrim" | "rtrim" | "round" => Some((1, Some(2))),
+            "instr" | "ifnull" | "nullif" => Some((2, Some(2))),
+            "substr" | "substring" => Some((2, Some(3))),
+            "replace" => Some((3, Some(3))),
+            "min" | "max" | "printf" | "format" => Some((1, None)),
+            "coalesce" => Some((2, None)),
+            _ => None,
+        }
+    }
+
+    pub fn check_arity(&self) -> Result<(), String> {
+        let n = self.args.len();
+
+        match self.arity() {
+            None => Err(format!("no such function: {}", self.name)),
+            Some((least, most)) if n < least || most.map_or(false, |most| n > most) => {
+                Err(format!("wrong number of arguments to function {}()", self.name))
+            }
+            _ => Ok(()),
         }
     }
 }
@@ -331,6 +878,7 @@ impl Expression {
 #[derive(Copy, Show, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum BinaryOperator {
     Null,
+    Concat,
     Mult,
     Divide,
     Modulo,
@@ -346,6 +894,15 @@ pub enum BinaryOperator {
     GreaterEq,
     Equals,
     NotEquals,
+    Like,
+    NotLike,
+    Glob,
+    NotGlob,
+    Between,
+    NotBetween,
+    In,
+    NotIn,
+    Escape,
     And,
     Or,
 }
@@ -359,23 +916,62 @@ impl BinaryOperator {
         }
     }
 
+    pub fn symbol(&self) -> &'static str {
+        match *self {
+            BinaryOperator::Null => "",
+            BinaryOperator::Concat => "||",
+            BinaryOperator::Mult => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+            BinaryOperator::Plus => "+",
+            BinaryOperator::Minus => "-",
+            BinaryOperator::LShift => "<<",
+            BinaryOperator::RShift => ">>",
+            BinaryOperator::BitAnd => "&",
+            BinaryOperator::BitOr => "|",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessEq => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterEq => ">=",
+            BinaryOperator::Equals => "=",
+            BinaryOperator::NotEquals => "!=",
+            BinaryOperator::Like => "LIKE",
+            BinaryOperator::NotLike => "NOT LIKE",
+            BinaryOperator::Glob => "GLOB",
+            BinaryOperator::NotGlob => "NOT GLOB",
+            BinaryOperator::Between => "BETWEEN",
+            BinaryOperator::NotBetween => "NOT BETWEEN",
+            BinaryOperator::In => "IN",
+            BinaryOperator::NotIn => "NOT IN",
+            BinaryOperator::Escape => "ESCAPE",
+            BinaryOperator::And => "AND",
+            BinaryOperator::Or => "OR",
+        }
+    }
+
     pub fn ord_val(&self) -> usize {
         match *self {
             BinaryOperator::Null => 0,
+            BinaryOperator::Concat => 1,
             BinaryOperator::Mult | BinaryOperator::Divide | BinaryOperator::Modulo => 2,
             BinaryOperator::Plus | BinaryOperator::Minus => 3,
             BinaryOperator::LShift | BinaryOperator::RShift
                 | BinaryOperator::BitAnd | BinaryOperator::BitOr => 4,
             BinaryOperator::Less | BinaryOperator::LessEq
                 | BinaryOperator::Greater | BinaryOperator::GreaterEq => 5,
-            BinaryOperator::Equals | BinaryOperator::NotEquals => 6,
+            BinaryOperator::Equals | BinaryOperator::NotEquals
+                | BinaryOperator::Like | BinaryOperator::NotLike
+                | BinaryOperator::Glob | BinaryOperator::NotGlob
+                | BinaryOperator::Between | BinaryOperator::NotBetween
+                | BinaryOperator::In | BinaryOperator::NotIn
+                | BinaryOperator::Escape => 6,
             BinaryOperator::And => 7,
             BinaryOperator::Or => 8,
         }
     }
 }
 
-#[derive(Copy, Show, Clone)]
+#[derive(Copy, Show, Clone, PartialEq)]
 pub enum UnaryOperator {
     Plus,
     Minus,
@@ -384,6 +980,15 @@ pub enum UnaryOperator {
 }
 
 impl UnaryOperator {
+    pub fn symbol(&self) -> &'static str {
+        match *self {
+            UnaryOperator::Plus => "+",
+            UnaryOperator::Minus => "-",
+            UnaryOperator::Not => "NOT ",
+            UnaryOperator::BitNeg => "~",
+        }
+    }
+
     pub fn neg(&self) -> UnaryOperator {
         match *self {
             UnaryOperator::Plus => UnaryOperator::Minus,
@@ -396,40 +1001,71 @@ impl UnaryOperator {
 pub struct DeleteDef {
     pub name: String,
     pub where_expr: Option<Expression>,
+    pub returning: Option<ResultColumn>,
 }
 
 pub struct UpdateDef {
     pub name: String,
-    pub set: Vec<(String, Expression)>,
+    pub set: Vec<ColumnAssignment>,
+    pub from_clause: Option<FromClause>,
     pub where_expr: Option<Expression>,
+    pub returning: Option<ResultColumn>,
 }
 
-#[derive(Clone)]
+// Several columns and values for `(a, b) = (x, y)`.
+pub type ColumnAssignment = (Vec<String>, Vec<Expression>);
+
+#[derive(Show, Clone, PartialEq)]
 pub struct OrderingTerm {
     pub expr: Expression,
     pub order: Order,
+    pub nulls: Nulls,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Show, Clone, PartialEq)]
 pub enum Order {
     Ascending,
     Descending,
 }
 
-pub type JoinClause = (JoinOperator, String, Option<JoinConstraint>);
+// By default NULLs come first in ascending order and last in descending order.
+#[derive(Copy, Show, Clone, PartialEq)]
+pub enum Nulls {
+    First,
+    Last,
+}
+
+pub type JoinClause = (JoinOperator, TableOrSubquery, Option<JoinConstraint>);
 
+#[derive(Show, Clone, PartialEq)]
 pub enum FromClause {
-    TableOrSubquery(Vec<String>),
-    JoinClause(String, Option<Vec<JoinClause>>),
+    TableOrSubquery(Vec<TableOrSubquery>),
+    JoinClause(TableOrSubquery, Option<Vec<JoinClause>>),
 }
 
-#[derive(Copy)]
+#[derive(Show, Clone, PartialEq)]
+pub enum TableOrSubquery {
+    Table((String, Option<String>)),
+    Subquery((Box<SelectDef>, Option<String>)),
+}
+
+#[derive(Copy, Show, Clone, PartialEq)]
 pub enum JoinOperator {
+    Join(JoinType),
+    Natural(JoinType),
+}
+
+#[derive(Copy, Show, Clone, PartialEq)]
+pub enum JoinType {
     Inner,
-    Natural,
+    Left,
+    Right,
+    Full,
+    Cross,
 }
 
-#[derive(Clone)]
+#[derive(Show, Clone, PartialEq)]
 pub enum JoinConstraint {
     On(Expression),
+    Using(Vec<String>),
 }
@@ -1,25 +1,80 @@
-use table::{TableRow, TableHeader, Table};
+use table::{TableRow, TableHeader, Table, InputTable, PkType, OrderedRow, cmp_rows, generated_order};
 use definitions::{ResultColumn, RusqlStatement, InsertDef, SelectDef};
-use definitions::{AlterTableDef, AlterTable, Expression, FromClause, JoinOperator};
+use definitions::{AlterTableDef, AlterTable, Expression, FromClause, JoinOperator, JoinType};
 use definitions::{DeleteDef, InsertDataSource, UpdateDef, Order, JoinConstraint};
-use definitions::{BinaryOperator};
-use expressions::{ExpressionResult, ExpressionEvaluator, expr_to_literal, result_to_literal};
-use rusql::Rusql;
+use definitions::{BinaryOperator, FunctionCall, LiteralValue, ColumnDef, TableOrSubquery, CompoundOperator};
+use definitions::{CompoundSelect, WithClause, CommonTableExpression};
+use definitions::{OrderingTerm, Nulls, Over, WindowDef, NamedWindow, FrameSpec, FrameUnits, FrameBound};
+use definitions::{JoinClause, ResultExpression};
+use expressions::{ExpressionResult, ExpressionEvaluator, result_to_literal};
+use expressions::eval_aggregate;
+use rusql::{Rusql, SEQUENCE_TABLE};
+
+use std::cmp::{max, min, Ordering};
+use std::collections::{BTreeMap, BTreeSet};
+use std::iter::repeat;
+use std::num::Int;
 
 peg_file! parser("sql.rustpeg");
 
-pub fn rusql_exec<F: FnMut(&TableRow, &TableHeader)>(db: &mut Rusql, sql_str: &str, callback: F) -> Option<Table> {
+pub fn rusql_exec<F: FnMut(&TableRow, &TableHeader)>(db: &mut Rusql, sql_str: &str, mut callback: F) -> Option<Table> {
     match parser::rusql_parse(sql_str) {
         Ok(res) => {
             for stmt in res.into_iter() {
-                match stmt {
-                    RusqlStatement::AlterTable(alter_table_def) => alter_table(db, alter_table_def),
-                    RusqlStatement::CreateTable(table_def) => db.create_table(table_def),
-                    RusqlStatement::Delete(delete_def) => delete(db, delete_def),
-                    RusqlStatement::DropTable(drop_table_def) => db.drop_table(&drop_table_def.name),
-                    RusqlStatement::Insert(insert_def) => insert(db, insert_def),
-                    RusqlStatement::Select(select_def) => return Some(select(db, select_def, callback)),
-                    RusqlStatement::Update(update_def) => update(db, update_def),
+                db.subquery_cache.borrow_mut().clear();
+                *db.error.borrow_mut() = None;
+
+                if let Err(e) = prepare(&stmt) {
+                    println!("error: {}", e);
+                    continue;
+                }
+
+                let mut shadowed: Vec<(String, Option<Table>)> = Vec::new();
+                let stmt = match stmt {
+                    RusqlStatement::With((with_clause, stmt)) => {
+                        if let Err(e) = create_ctes(db, &with_clause, &mut shadowed) {
+                            println!("error: {}", e);
+                            drop_ctes(db, shadowed);
+                            continue;
+                        }
+                        *stmt
+                    }
+                    stmt => stmt,
+                };
+
+                // A SELECT, or a statement with a RETURNING clause, gives a result table.
+                let results_table = match stmt {
+                    RusqlStatement::AlterTable(alter_table_def) => {
+                        alter_table(db, alter_table_def);
+                        None
+                    }
+                    RusqlStatement::CreateTable(table_def) => {
+                        db.create_table(table_def);
+                        None
+                    }
+                    RusqlStatement::Delete(delete_def) => delete(db, delete_def, &mut callback),
+                    RusqlStatement::DropTable(drop_table_def) => {
+                        db.drop_table(&drop_table_def.name);
+                        None
+                    }
+                    RusqlStatement::Insert(insert_def) => insert(db, insert_def, &mut callback),
+                    RusqlStatement::Select(select_def) => {
+                        Some(select(db, &select_def, None, |row, header| callback(row, header)))
+                    }
+                    RusqlStatement::Update(update_def) => update(db, update_def, &mut callback),
+                    RusqlStatement::With(..) => None,
+                };
+
+                drop_ctes(db, shadowed);
+
+                let error = db.error.borrow_mut().take();
+                if let Some(e) = error {
+                    println!("error: {}", e);
+                    continue;
+                }
+
+                if results_table.is_some() {
+                    return results_table;
                 }
             }
         }
@@ -28,284 +83,1890 @@ pub fn rusql_exec<F: FnMut(&TableRow, &TableHeader)>(db: &mut Rusql, sql_str: &s
     None
 }
 
-fn alter_table(db: &mut Rusql, alter_table_def: AlterTableDef) {
-    match alter_table_def.mode {
-        AlterTable::RenameTo(new_name) => db.rename_table(&alter_table_def.name, new_name),
-        AlterTable::AddColumn(column_def) => db.get_mut_table(&alter_table_def.name)
-                                               .add_column(column_def),
+fn prepare(stmt: &RusqlStatement) -> Result<(), String> {
+    match *stmt {
+        RusqlStatement::AlterTable(ref alter_table_def) if alter_table_def.name.as_slice() == SEQUENCE_TABLE => {
+            Err(format!("table {} may not be altered", SEQUENCE_TABLE))
+        }
+        RusqlStatement::AlterTable(AlterTableDef { mode: AlterTable::AddColumn(ref column_def), .. }) => {
+            check_column_def(column_def)
+        }
+        RusqlStatement::CreateTable(ref table_def) => {
+            for column_def in table_def.columns.iter() {
+                try!(check_column_def(column_def));
+            }
+            check_generated(&table_def.columns)
+        }
+        RusqlStatement::DropTable(ref drop_table_def) if drop_table_def.name.as_slice() == SEQUENCE_TABLE => {
+            Err(format!("table {} may not be dropped", SEQUENCE_TABLE))
+        }
+        RusqlStatement::Delete(ref delete_def) => {
+            try!(check_exprs(delete_def.where_expr.iter()));
+            try!(check_no_aggregates(delete_def.where_expr.iter()));
+            check_returning(&delete_def.returning)
+        }
+        RusqlStatement::Insert(ref insert_def) => {
+            try!(match insert_def.data_source {
+                InsertDataSource::Values(ref rows) => {
+                    try!(check_exprs(rows.iter().flat_map(|row| row.iter())));
+                    try!(check_no_aggregates(rows.iter().flat_map(|row| row.iter())));
+                    check_no_columns(rows.iter().flat_map(|row| row.iter()))
+                }
+                InsertDataSource::Select(ref select_def) => check_select(select_def),
+                _ => Ok(()),
+            });
+            check_returning(&insert_def.returning)
+        }
+        RusqlStatement::Select(ref select_def) => check_select(select_def),
+        RusqlStatement::Update(ref update_def) => {
+            try!(check_assignments(update_def));
+            try!(check_select(&update_select(update_def)));
+            check_returning(&update_def.returning)
+        }
+        RusqlStatement::With((ref with_clause, ref stmt)) => {
+            let target = match **stmt {
+                RusqlStatement::Delete(ref delete_def) => Some(&delete_def.name),
+                RusqlStatement::Insert(ref insert_def) => Some(&insert_def.table_name),
+                RusqlStatement::Update(ref update_def) => Some(&update_def.name),
+                _ => None,
+            };
+
+            // The CTEs shadow the tables of their names while the statement runs,
+            // so what it wrote to one would be lost.
+            if let Some(name) = target {
+                if with_clause.ctes.iter().any(|cte| &cte.name == name) {
+                    return Err(format!("cannot modify {} because it is a common table expression", name));
+                }
+            }
+
+            for cte in with_clause.ctes.iter() {
+                try!(check_select(&cte.select_def));
+            }
+            prepare(&**stmt)
+        }
+        _ => Ok(()),
     }
 }
 
-fn delete(db: &mut Rusql, delete_def: DeleteDef) {
-    let table = db.get_mut_table(&delete_def.name);
-
-    if let Some(ref expr) = delete_def.where_expr {
-        // FIXME just making the borrow checker happy...
-        let header = table.header.clone();
-        table.delete_where(|row| ExpressionEvaluator::new(row, &header).eval_bool(expr));
-    } else {
-        table.clear();
+fn check_column_def(column_def: &ColumnDef) -> Result<(), String> {
+    match column_def.generated() {
+        Some((expr, _)) => check_expr(expr),
+        None => Ok(()),
     }
 }
 
-fn insert(db: &mut Rusql, insert_def: InsertDef) {
-    match insert_def.data_source {
-        InsertDataSource::Values(column_data) => {
-            let mut table = db.get_mut_table(&insert_def.table_name);
-            table.insert(column_data, &insert_def.column_names);
+// Generated columns may only refer to the columns of their own table.
+fn check_generated(columns: &TableHeader) -> Result<(), String> {
+    for column_def in columns.iter() {
+        if let Some((expr, _)) = column_def.generated() {
+            try!(check_generated_expr(expr, columns));
         }
-        InsertDataSource::Select(select_def) => {
-            let results_table = select(db, select_def, |_,_| {});
-            let mut table = db.get_mut_table(&insert_def.table_name);
+    }
+    generated_order(columns).map(|_| ())
+}
 
-            for (_, row) in results_table.data.into_iter() {
-                table.push_row(row);
+fn check_generated_expr(expr: &Expression, columns: &TableHeader) -> Result<(), String> {
+    match *expr {
+        Expression::ColumnName(ref name) if columns.iter().any(|column_def| &column_def.name == name) => Ok(()),
+        Expression::ColumnName(_) | Expression::TableName(_) => Err(format!("no such column: {}", expr)),
+        Expression::Function(ref call) if call.is_aggregate() => {
+            Err(format!("misuse of aggregate function {}()", call.name))
+        }
+        Expression::Function(ref call) => {
+            for arg in call.args.iter() {
+                try!(check_generated_expr(arg, columns));
             }
+            Ok(())
         }
-        _ => {}
+        Expression::WindowFunction((ref call, _)) => Err(format!("misuse of window function {}()", call.name)),
+        Expression::Subquery(_) | Expression::Exists(_) | Expression::InSelect(_) => {
+            Err("subqueries prohibited in generated columns".to_string())
+        }
+        Expression::UnaryOperator((_, ref expr)) | Expression::Cast((ref expr, _)) => {
+            check_generated_expr(&**expr, columns)
+        }
+        Expression::BinaryOperator((_, ref left, ref right)) => {
+            try!(check_generated_expr(&**left, columns));
+            check_generated_expr(&**right, columns)
+        }
+        Expression::Case((ref base, ref branches, ref else_expr)) => {
+            for expr in base.iter().chain(else_expr.iter()) {
+                try!(check_generated_expr(&**expr, columns));
+            }
+            for &(ref when, ref then) in branches.iter() {
+                try!(check_generated_expr(when, columns));
+                try!(check_generated_expr(then, columns));
+            }
+            Ok(())
+        }
+        Expression::List(ref exprs) => {
+            for expr in exprs.iter() {
+                try!(check_generated_expr(expr, columns));
+            }
+            Ok(())
+        }
+        _ => Ok(()),
     }
 }
 
-fn update(db: &mut Rusql, update_def: UpdateDef) {
-    let mut table = db.get_mut_table(&update_def.name);
-
-    for (_, row) in table.data.iter_mut() {
-        if let Some(ref expr) = update_def.where_expr {
-            if !ExpressionEvaluator::new(row, &table.header).eval_bool(expr) {
-                continue;
-            }
+fn check_returning(returning: &Option<ResultColumn>) -> Result<(), String> {
+    match *returning {
+        Some(ResultColumn::Expressions(ref exprs)) => {
+            try!(check_exprs(exprs.iter().map(|&(ref expr, _)| expr)));
+            check_no_aggregates(exprs.iter().map(|&(ref expr, _)| expr))
         }
+        _ => Ok(()),
+    }
+}
 
-        for &(ref name, ref expr) in update_def.set.iter() {
-            let x = table.header.iter().position(|ref cols| &cols.name == name).unwrap();
+// Aggregate or window functions would make a grouped or windowed query of
+// `update_select`.
+fn check_assignments(update_def: &UpdateDef) -> Result<(), String> {
+    for expr in update_def.set.iter().flat_map(|&(_, ref exprs)| exprs.iter()) {
+        try!(check_no_aggregates(Some(expr).into_iter()));
 
-            row[x] = expr_to_literal(expr);
+        let mut calls: Vec<(&FunctionCall, &Over)> = Vec::new();
+        collect_window_functions(expr, &mut calls);
+        if let Some(&(call, _)) = calls.first() {
+            return Err(format!("misuse of window function {}()", call.name));
         }
     }
+    Ok(())
 }
 
-fn product(tables: Vec<&Table>, input_product: &mut Table, new_row_opt: Option<TableRow>) {
-    let mut remaining = tables.clone();
+fn check_select(select_def: &SelectDef) -> Result<(), String> {
+    if let ResultColumn::Expressions(ref exprs) = select_def.result_column {
+        try!(check_exprs(exprs.iter().map(|&(ref expr, _)| expr)));
+    }
 
-    if remaining.len() == 0 {
-        if let Some(new_row) = new_row_opt {
-            input_product.push_row(new_row);
+    for source in from_sources(select_def).into_iter() {
+        if let &TableOrSubquery::Subquery((ref select_def, _)) = source {
+            try!(check_select(&**select_def));
         }
-    } else {
-        let table = remaining.remove(0);
-        for row in table.data.values() {
-            let mut new_row: TableRow = if let Some(ref new_row) = new_row_opt {
-                new_row.clone()
-            } else {
-                Vec::new()
-            };
+    }
+
+    if let Some(FromClause::JoinClause(_, Some(ref join_clauses))) = select_def.from_clause {
+        let constraints: Vec<&Expression> = join_clauses.iter().filter_map(|&(_, _, ref constraint)| match *constraint {
+            Some(JoinConstraint::On(ref expr)) => Some(expr),
+            _ => None,
+        }).collect();
+        try!(check_exprs(constraints.iter().map(|expr| *expr)));
+        try!(check_no_aggregates(constraints.iter().map(|expr| *expr)));
+    }
+
+    try!(check_exprs(select_def.where_expr.iter()));
+    try!(check_no_aggregates(select_def.where_expr.iter()));
+    try!(check_exprs(select_def.group_by.iter().flat_map(|exprs| exprs.iter())));
+    try!(check_no_aggregates(select_def.group_by.iter().flat_map(|exprs| exprs.iter())));
+    try!(check_exprs(select_def.having.iter()));
 
-            new_row.push_all(&*row.clone());
+    // Window functions are computed over the rows of the input product, which an
+    // aggregate query doesn't return.
+    if select_def.group_by.is_some() || is_aggregate_query(select_def) {
+        let mut exprs: Vec<Expression> = select_def.having.iter().map(|expr| expr.clone()).collect();
+        exprs.extend(select_def.ordering_terms.iter().flat_map(|terms| terms.iter().map(|term| term.expr.clone())));
 
-            product(remaining.clone(), input_product, Some(new_row));
+        if let Some(&(call, _)) = window_functions(select_def, &exprs).first() {
+            return Err(format!("window function {}() can't be used in an aggregate query", call.name));
         }
     }
+
+    for &(ref name, ref window) in select_def.windows.iter() {
+        try!(check_window(window));
+        try!(resolve_window(&Over::Name(name.clone()), select_def.windows.as_slice()));
+    }
+
+    let order_exprs: Vec<Expression> = select_def.ordering_terms.iter().flat_map(|terms| {
+        terms.iter().map(|term| term.expr.clone())
+    }).collect();
+    for &(_, over) in window_functions(select_def, &order_exprs).iter() {
+        try!(resolve_window(over, select_def.windows.as_slice()));
+    }
+
+    for &(_, ref core) in select_def.compound.iter() {
+        try!(check_select(core));
+    }
+
+    try!(check_exprs(select_def.ordering_terms.iter().flat_map(|terms| terms.iter().map(|term| &term.expr))));
+    try!(check_exprs(select_def.limit.iter().chain(select_def.offset.iter())));
+    check_no_aggregates(select_def.limit.iter().chain(select_def.offset.iter()))
 }
 
-fn select<F: FnMut(&TableRow, &TableHeader)>(db: &mut Rusql, select_def: SelectDef, mut callback: F) -> Table {
-    let mut input_tables: Vec<&Table> = Vec::new();
-    let mut input_product = generate_inputs(db, &mut input_tables, &select_def);
+fn check_window(window: &WindowDef) -> Result<(), String> {
+    try!(check_exprs(window.partition_by.iter().flat_map(|exprs| exprs.iter())));
+    try!(check_exprs(window.ordering_terms.iter().flat_map(|terms| terms.iter().map(|term| &term.expr))));
 
-    filter_inputs(&mut input_product, &input_tables, &select_def);
+    if let Some(ref frame) = window.frame {
+        for bound in [&frame.start, &frame.end].iter() {
+            match **bound {
+                FrameBound::Preceding(ref expr) | FrameBound::Following(ref expr) => try!(check_expr(expr)),
+                _ => {}
+            }
+        }
+    }
 
-    let results_table = generate_result_set(input_product, &input_tables, &select_def);
+    Ok(())
+}
 
-    for row in results_table.data.values() {
-        callback(row, &results_table.header);
+// Aggregates are only computed for the result columns, HAVING and ORDER BY.
+fn check_no_aggregates<'a, I: Iterator<Item=&'a Expression>>(exprs: I) -> Result<(), String> {
+    for expr in exprs {
+        if let Some(call) = expr.aggregate_call() {
+            return Err(format!("misuse of aggregate function {}()", call.name));
+        }
     }
+    Ok(())
+}
 
-    results_table
+// VALUES are evaluated without any tables, so they can't refer to columns.
+fn check_no_columns<'a, I: Iterator<Item=&'a Expression>>(exprs: I) -> Result<(), String> {
+    for expr in exprs {
+        let mut refs: Vec<&Expression> = Vec::new();
+        expr.column_refs(&mut refs);
+        if let Some(column_ref) = refs.first() {
+            return Err(format!("no such column: {}", column_ref));
+        }
+    }
+    Ok(())
 }
 
-fn natural_join(tables: &Vec<&Table>, constraints: &mut Vec<JoinConstraint>) {
-    // FIXME ...
-    let mut columns: Vec<(String, String, String)> = Vec::new();
+fn check_exprs<'a, I: Iterator<Item=&'a Expression>>(exprs: I) -> Result<(), String> {
+    for expr in exprs {
+        try!(check_expr(expr));
+    }
+    Ok(())
+}
 
-    for table in tables.iter() {
-        for other in tables.iter() {
-            if table == other {
-                continue;
+fn check_expr(expr: &Expression) -> Result<(), String> {
+    match *expr {
+        Expression::Function(ref call) => {
+            try!(call.check_arity());
+            check_exprs(call.args.iter())
+        }
+        Expression::WindowFunction((ref call, ref over)) => {
+            if !call.is_window_function() {
+                return Err(format!("no such window function: {}", call.name));
             }
+            try!(check_exprs(call.args.iter()));
 
-            for col in table.header.iter() {
-                for other_col in other.header.iter() {
-                    if col.name == other_col.name {
-                        columns.push((table.name.clone(), other.name.clone(), other_col.name.clone()));
-                    }
+            match *over {
+                Over::Window(ref window) => check_window(window),
+                Over::Name(_) => Ok(()),
+            }
+        }
+        Expression::TableName((_, ref expr)) | Expression::UnaryOperator((_, ref expr))
+            | Expression::Cast((ref expr, _)) => check_expr(&**expr),
+        Expression::BinaryOperator((operator, ref left, ref right)) => {
+            if let (BinaryOperator::Escape, &Expression::LiteralValue(ref escape)) = (operator, &**right) {
+                if escape.to_text().chars().count() != 1 {
+                    return Err("ESCAPE expression must be a single character".to_string());
                 }
             }
+            try!(check_expr(&**left));
+            check_expr(&**right)
+        }
+        Expression::Case((ref base, ref branches, ref else_expr)) => {
+            try!(check_exprs(base.iter().chain(else_expr.iter()).map(|expr| &**expr)));
+            try!(check_exprs(branches.iter().map(|&(ref when, _)| when)));
+            check_exprs(branches.iter().map(|&(_, ref then)| then))
         }
+        Expression::Subquery(ref select_def) => {
+            try!(check_single_column(&**select_def));
+            check_select(&**select_def)
+        }
+        Expression::Exists(ref select_def) => check_select(&**select_def),
+        Expression::InSelect((ref expr, ref select_def, _)) => {
+            try!(check_expr(&**expr));
+            try!(check_single_column(&**select_def));
+            check_select(&**select_def)
+        }
+        Expression::List(ref exprs) => check_exprs(exprs.iter()),
+        _ => Ok(()),
     }
+}
 
-    for (table1, table2, column_name) in columns.into_iter() {
-        constraints.push(JoinConstraint::On(
-                Expression::BinaryOperator((BinaryOperator::Equals,
-                    box Expression::TableName((table1, box Expression::ColumnName(column_name.clone()))),
-                    box Expression::TableName((table2, box Expression::ColumnName(column_name)))))));
+// With * the width is up to the tables, and is checked as the subquery runs.
+fn check_single_column(select_def: &SelectDef) -> Result<(), String> {
+    match select_def.result_column {
+        ResultColumn::Expressions(ref exprs) if exprs.len() != 1 => {
+            Err(format!("sub-select returns {} columns - expected 1", exprs.len()))
+        }
+        _ => Ok(()),
     }
 }
 
-fn generate_inputs<'a>(db: &'a Rusql, input_tables: &mut Vec<&'a Table>, select_def: &SelectDef) -> Table {
-    // https://www.sqlite.org/lang_select.html#fromclause
-    let mut input_header: TableHeader = Vec::new();
+// Tables of the same names are shadowed, and recorded in `shadowed` for
+// `drop_ctes` to restore.
+fn create_ctes(db: &mut Rusql, with_clause: &WithClause, shadowed: &mut Vec<(String, Option<Table>)>) -> Result<(), String> {
+    // https://www.sqlite.org/lang_with.html
+    for cte in with_clause.ctes.iter() {
+        let recursive = with_clause.recursive && cte.select_def.compound.iter().any(|&(_, ref core)| {
+            refers_to(core, &cte.name)
+        });
 
-    if let Some(ref from_clause) = select_def.from_clause {
-        // FIXME CLEANUP PLZ
-        match from_clause {
-            &FromClause::TableOrSubquery(ref table_or_subquery) => {
-                for name in table_or_subquery.iter() {
-                    let table = db.get_table(name);
-                    input_tables.push(table);
-                    input_header.push_all(&*table.header.clone());
-                }
+        let mut table = if recursive {
+            try!(recursive_cte(db, cte))
+        } else {
+            select(db, &cte.select_def, None, |_,_| {})
+        };
 
-                let mut input_product = Table::new_result_table(input_header);
+        try!(name_cte_columns(cte, &mut table.header));
+        table.name = cte.name.clone();
 
-                product(input_tables.clone(), &mut input_product, None);
+        let shadowed_table = db.map.remove(cte.name.as_slice());
+        shadowed.push((cte.name.clone(), shadowed_table));
+        db.map.insert(cte.name.clone(), table);
+        // Cached subquery results may have been read from the table it shadows.
+        db.subquery_cache.borrow_mut().clear();
+    }
 
-                input_product
-            },
-            &FromClause::JoinClause(ref name, ref join_clauses) => {
-                let table = db.get_table(name);
-                let mut constraints: Vec<JoinConstraint> = Vec::new();
-                input_tables.push(table);
-                input_header.push_all(&*table.header.clone());
+    Ok(())
+}
 
-                if let &Some(ref join_clauses) = join_clauses {
-                    for &(ref join_operator, ref name, ref join_const) in join_clauses.iter() {
-                        let table = db.get_table(name);
-                        input_tables.push(table);
-                        input_header.push_all(&*table.header.clone());
+fn drop_ctes(db: &mut Rusql, shadowed: Vec<(String, Option<Table>)>) {
+    for (name, table) in shadowed.into_iter().rev() {
+        db.map.remove(name.as_slice());
 
-                        if let &Some(ref constraint) = join_const {
-                            constraints.push(constraint.clone());
-                        }
+        if let Some(table) = table {
+            db.map.insert(name, table);
+        }
+    }
+}
 
-                        match join_operator {
-                            &JoinOperator::Natural => natural_join(input_tables, &mut constraints),
-                            _ => {}
-                        }
-                    }
-                }
+fn name_cte_columns(cte: &CommonTableExpression, header: &mut TableHeader) -> Result<(), String> {
+    if let Some(ref column_names) = cte.column_names {
+        if column_names.len() != header.len() {
+            return Err(format!("table {} has {} values for {} columns", cte.name, header.len(), column_names.len()));
+        }
+
+        for (column, name) in header.iter_mut().zip(column_names.iter()) {
+            column.name = name.clone();
+        }
+    }
 
-                let mut input_product = Table::new_result_table(input_header);
+    Ok(())
+}
 
-                product(input_tables.clone(), &mut input_product, None);
+// The SELECTs that don't refer to the CTE give its initial rows; the rest are run
+// against the rows added by the last iteration, until an iteration adds none.
+fn recursive_cte(db: &mut Rusql, cte: &CommonTableExpression) -> Result<Table, String> {
+    let select_def = &cte.select_def;
 
-                let header = input_product.header.clone();
+    let mut initial = select_def.clone();
+    initial.compound = select_def.compound.iter().take_while(|&&(_, ref core)| !refers_to(core, &cte.name))
+                                                 .map(|compound| compound.clone()).collect();
+    initial.ordering_terms = None;
+    initial.limit = None;
+    initial.offset = None;
 
-                for constraint in constraints.into_iter() {
-                    match constraint {
-                        JoinConstraint::On(ref expr) => {
-                            input_product.delete_where(|row| {
-                                !ExpressionEvaluator::new(row, &header).with_tables(input_tables.clone())
-                                                                       .eval_bool(expr)
-                            });
-                        }
-                    }
+    let recursive: Vec<&CompoundSelect> = select_def.compound.iter().skip(initial.compound.len()).collect();
+
+    for &&(operator, _) in recursive.iter() {
+        if operator != CompoundOperator::Union && operator != CompoundOperator::UnionAll {
+            return Err(format!("recursive table {} must be joined with UNION or UNION ALL", cte.name));
+        }
+    }
+
+    let (limit, offset) = eval_limit(db, None, select_def);
+    let initial_table = select(db, &initial, None, |_,_| {});
+    let mut header = initial_table.header.clone();
+    try!(name_cte_columns(cte, &mut header));
+
+    let mut rows: Vec<TableRow> = initial_table.data.into_iter().map(|(_, row)| row).collect();
+    if recursive.iter().any(|&&(operator, _)| operator == CompoundOperator::Union) {
+        rows = distinct_rows(rows);
+    }
+
+    // While iterating, the table of the CTE's name holds the rows of the last iteration.
+    let shadowed_table = db.map.remove(cte.name.as_slice());
+    let result = iterate_cte(db, cte, &header, &mut rows, &recursive, limit.map(|limit| limit + offset));
+
+    db.map.remove(cte.name.as_slice());
+    if let Some(table) = shadowed_table {
+        db.map.insert(cte.name.clone(), table);
+    }
+    try!(result);
+
+    let mut table = Table::new_result_table(header);
+    let count = rows.len();
+
+    for row in rows.into_iter().skip(offset).take(limit.unwrap_or(count)) {
+        table.push_row(row);
+    }
+
+    Ok(table)
+}
+
+fn iterate_cte(db: &mut Rusql, cte: &CommonTableExpression, header: &TableHeader, rows: &mut Vec<TableRow>,
+               recursive: &Vec<&CompoundSelect>, scan_limit: Option<usize>) -> Result<(), String> {
+    let mut queue = rows.clone();
+    let mut seen: BTreeSet<OrderedRow> = rows.iter().map(|row| OrderedRow(row.clone())).collect();
+
+    while queue.len() > 0 && scan_limit.map_or(true, |limit| rows.len() < limit) {
+        let mut queue_table = Table::new_result_table(header.clone());
+        queue_table.name = cte.name.clone();
+        for row in queue.into_iter() {
+            queue_table.push_row(row);
+        }
+        db.map.insert(cte.name.clone(), queue_table);
+        db.subquery_cache.borrow_mut().clear();
+
+        let mut added: Vec<TableRow> = Vec::new();
+
+        for &&(operator, ref core) in recursive.iter() {
+            let (core_header, core_rows) = select_core(db, None, core, &Vec::new(), None);
+
+            if core_header.len() != header.len() {
+                return Err(format!("SELECTs to the left and right of {} do not have the same number of result columns",
+                                   operator));
+            }
+
+            for row in core_rows.into_iter() {
+                if operator == CompoundOperator::UnionAll || seen.insert(OrderedRow(row.clone())) {
+                    added.push(row);
                 }
+            }
+        }
 
-                input_product
-            },
+        rows.push_all(added.as_slice());
+        queue = added;
+    }
+
+    Ok(())
+}
+
+fn refers_to(select_def: &SelectDef, name: &String) -> bool {
+    from_sources(select_def).iter().any(|source| match *source {
+        &TableOrSubquery::Table((ref table_name, _)) => table_name == name,
+        _ => false,
+    })
+}
+
+fn alter_table(db: &mut Rusql, alter_table_def: AlterTableDef) {
+    match alter_table_def.mode {
+        AlterTable::RenameTo(new_name) => db.rename_table(&alter_table_def.name, new_name),
+        AlterTable::AddColumn(column_def) => {
+            let mut columns = db.get_table(&alter_table_def.name).header.clone();
+            columns.push(column_def.clone());
+
+            if let Err(e) = check_generated(&columns) {
+                println!("error: {}", e);
+                return;
+            }
+            db.get_mut_table(&alter_table_def.name).add_column(column_def);
         }
-    } else {
-       let mut input_product = Table::new_result_table(input_header);
-       let empty_row: TableRow = Vec::new();
-       input_product.push_row(empty_row);
+    }
+}
+
+fn delete<F: FnMut(&TableRow, &TableHeader)>(db: &mut Rusql, delete_def: DeleteDef,
+                                              callback: &mut F) -> Option<Table> {
+    let keys = match delete_def.where_expr {
+        Some(ref expr) => matching_keys(db, &delete_def.name, expr),
+        None => db.get_table(&delete_def.name).data.keys().map(|key| *key).collect(),
+    };
+    if db.failed() {
+        return None;
+    }
+
+    // RETURNING sees the rows as they were before being deleted.
+    let results_table = match delete_def.returning {
+        Some(ref result_column) => Some(returning(db, &delete_def.name, result_column, &keys, callback)),
+        None => None,
+    };
+
+    db.changes = keys.len();
 
-       input_product
+    let table = db.get_mut_table(&delete_def.name);
+    for key in keys.iter() {
+        table.data.remove(key);
     }
+
+    results_table
 }
 
-fn filter_inputs(input_product: &mut Table, input_tables: &Vec<&Table>, select_def: &SelectDef) {
-    // https://www.sqlite.org/lang_select.html#whereclause
+fn insert<F: FnMut(&TableRow, &TableHeader)>(db: &mut Rusql, insert_def: InsertDef,
+                                              callback: &mut F) -> Option<Table> {
+    db.load_sequence(&insert_def.table_name);
 
-    if let Some(ref expr) = select_def.where_expr {
-        let header = input_product.header.clone();
-        input_product.delete_where(|row| {
-            !ExpressionEvaluator::new(row, &header).with_tables(input_tables.clone())
-                                                   .eval_bool(expr)
-        });
+    // The rows are all computed before any is inserted, so an INSERT can select
+    // from the table it inserts into.
+    let (column_data, width) = match insert_def.data_source {
+        InsertDataSource::Values(rows) => {
+            let width = rows[0].len();
+            if rows.iter().any(|row| row.len() != width) {
+                println!("error: all VALUES must have the same number of terms");
+                return None;
+            }
+
+            // VALUES can't refer to any columns.
+            let empty_row: TableRow = Vec::new();
+            let empty_header: TableHeader = Vec::new();
+            let column_data: Vec<TableRow> = rows.iter().map(|row| {
+                row.iter().map(|expr| eval_row(&*db, None, &empty_row, &empty_header, &Vec::new(), expr))
+                          .collect::<TableRow>()
+            }).collect();
+
+            (column_data, width)
+        }
+        InsertDataSource::Select(select_def) => {
+            let results_table = select(db, &select_def, None, |_,_| {});
+            let width = results_table.header.len();
+
+            (results_table.data.into_iter().map(|(_, row)| row).collect(), width)
+        }
+        _ => return None,
+    };
+    if db.failed() {
+        return None;
+    }
+
+    let keys = match db.get_mut_table(&insert_def.table_name).insert(column_data, width, &insert_def.column_names) {
+        Ok(keys) => keys,
+        Err(e) => {
+            println!("error: {}", e);
+            return None;
+        }
+    };
+
+    db.changes = keys.len();
+    if let Some(rowid) = keys.last() {
+        db.last_insert_rowid = *rowid;
+    }
+    db.store_sequence(&insert_def.table_name);
+
+    match insert_def.returning {
+        Some(ref result_column) => Some(returning(db, &insert_def.table_name, result_column, &keys, callback)),
+        None => None,
     }
 }
 
-fn generate_result_set(input_product: Table, input_tables: &Vec<&Table>, select_def: &SelectDef) -> Table {
-    // https://www.sqlite.org/lang_select.html#resultset
-    let results_header: TableHeader = Vec::new();
-    let mut results_table = Table::new_result_table(results_header);
-    let mut rows: Vec<TableRow> = Vec::new();
+// The SET expressions of a row are all evaluated against the row as it was
+// before the update.
+fn update<F: FnMut(&TableRow, &TableHeader)>(db: &mut Rusql, update_def: UpdateDef,
+                                              callback: &mut F) -> Option<Table> {
+    // https://www.sqlite.org/lang_update.html
+    let mut columns: Vec<usize> = Vec::new();
+
+    for &(ref names, ref exprs) in update_def.set.iter() {
+        if names.len() != exprs.len() {
+            println!("error: {} columns assigned {} values", names.len(), exprs.len());
+            return None;
+        }
+
+        let table = db.get_table(&update_def.name);
+        for name in names.iter() {
+            if let Err(e) = table.check_writable(name) {
+                println!("error: {}", e);
+                return None;
+            }
 
-    for row in input_product.data.values() {
-        match select_def.result_column {
-            ResultColumn::Expressions(ref exprs) => generate_row_from_expressions(&mut results_table, row, exprs, input_tables),
-            ResultColumn::Asterisk => {
-                if results_table.header.len() == 0 {
-                    results_table.header = input_product.header.clone();
+            match table.get_input_column_index(name) {
+                Some(x) => columns.push(x),
+                None => {
+                    println!("error: no such column: {}", name);
+                    return None;
                 }
-                rows.push(row.clone());
             }
         }
     }
 
-    if let Some(ref ordering_terms) = select_def.ordering_terms {
-        debug!("ORDER BY");
-        let mut ordering_terms = ordering_terms.clone();
-        ordering_terms.as_mut_slice().reverse();
-        for term in ordering_terms.iter() {
-            rows.as_mut_slice().sort_by(|a, b| {
-                // FIXME ... ugly.
-                match term.order {
-                    Order::Ascending => a[result_to_literal(
-                            ExpressionEvaluator::new(a, &results_table.header)
-                                                 .as_column_alias()
-                                                 .eval_expr(&term.expr)
-                        ).to_uint()].cmp(&b[result_to_literal(
-                            ExpressionEvaluator::new(b, &results_table.header)
-                                                 .as_column_alias()
-                                                 .eval_expr(&term.expr)
-                        ).to_uint()]),
-                    Order::Descending => b[result_to_literal(
-                            ExpressionEvaluator::new(b, &results_table.header)
-                                                 .as_column_alias()
-                                                 .eval_expr(&term.expr)
-                        ).to_uint()].cmp(&a[result_to_literal(
-                            ExpressionEvaluator::new(a, &results_table.header)
-                                                 .as_column_alias()
-                                                 .eval_expr(&term.expr)
-                        ).to_uint()]),
+    // With a FROM clause a row may be matched more than once, and only its first
+    // match is used. Each row maps to the key it moves to and its new values.
+    let mut rows: BTreeMap<PkType, (PkType, TableRow)> = BTreeMap::new();
+    {
+        let table = db.get_table(&update_def.name);
+
+        for (_, values) in select(&*db, &update_select(&update_def), None, |_,_| {}).data.into_iter() {
+            // A RIGHT or FULL JOIN in the FROM clause can give rows without a row of
+            // the table.
+            if values[0] == LiteralValue::Null {
+                continue;
+            }
+
+            let key = values[0].to_uint();
+            if rows.contains_key(&key) {
+                continue;
+            }
+
+            let mut row = match table.data.get(&key) {
+                Some(row) => row.clone(),
+                None => continue,
+            };
+
+            // Without an INTEGER PRIMARY KEY, the rowid is assigned as the column
+            // after the table's own.
+            row.push(LiteralValue::Integer(key as isize));
+            for (x, value) in columns.iter().zip(values.into_iter().skip(1)) {
+                row[*x] = value;
+            }
+            let rowid = row.pop().unwrap();
+
+            let new_key = match table.pk.map_or(rowid, |i| row[i].clone()) {
+                LiteralValue::Null => {
+                    println!("error: datatype mismatch");
+                    return None;
                 }
-            });
+                rowid => rowid.to_uint(),
+            };
+            rows.insert(key, (new_key, row));
         }
     }
-    results_table.insert(rows, &None);
 
-    results_table
+    if db.failed() {
+        return None;
+    }
+
+    let keys: Vec<PkType> = rows.values().map(|&(new_key, _)| new_key).collect();
+    let rows: Vec<(PkType, PkType, TableRow)> = rows.into_iter().map(|(key, (new_key, row))| {
+        (key, new_key, row)
+    }).collect();
+
+    if let Err(e) = db.get_mut_table(&update_def.name).update_rows(rows) {
+        println!("error: {}", e);
+        return None;
+    }
+
+    db.changes = keys.len();
+
+    match update_def.returning {
+        Some(ref result_column) => Some(returning(db, &update_def.name, result_column, &keys, callback)),
+        None => None,
+    }
 }
 
-fn generate_row_from_expressions(results_table: &mut Table, row: &TableRow, exprs: &Vec<Expression>, input_tables: &Vec<&Table>) {
-    let mut new_row: TableRow = Vec::new();
-    let push_header = if results_table.header.len() == 0 { true } else { false };
+// Selects the rowid of each row to change, followed by its new values in the
+// order they are assigned.
+fn update_select(update_def: &UpdateDef) -> SelectDef {
+    let rowid = Expression::TableName((update_def.name.clone(), box Expression::ColumnName("rowid".to_string())));
+    let mut exprs: Vec<ResultExpression> = vec![(rowid, None)];
+
+    for &(_, ref values) in update_def.set.iter() {
+        exprs.extend(values.iter().map(|expr| (expr.clone(), None)));
+    }
+
+    let cross = JoinOperator::Join(JoinType::Cross);
+    let joins: Vec<JoinClause> = match update_def.from_clause {
+        Some(FromClause::TableOrSubquery(ref sources)) => {
+            sources.iter().map(|source| (cross, source.clone(), None)).collect()
+        }
+        Some(FromClause::JoinClause(ref source, ref join_clauses)) => {
+            let mut joins = vec![(cross, source.clone(), None)];
+            if let &Some(ref join_clauses) = join_clauses {
+                joins.push_all(join_clauses.as_slice());
+            }
+            joins
+        }
+        None => Vec::new(),
+    };
+
+    SelectDef {
+        distinct: false,
+        result_column: ResultColumn::Expressions(exprs),
+        from_clause: Some(FromClause::JoinClause(TableOrSubquery::Table((update_def.name.clone(), None)), Some(joins))),
+        where_expr: update_def.where_expr.clone(),
+        group_by: None,
+        having: None,
+        windows: Vec::new(),
+        compound: Vec::new(),
+        ordering_terms: None,
+        limit: None,
+        offset: None,
+    }
+}
+
+fn returning<F: FnMut(&TableRow, &TableHeader)>(db: &Rusql, name: &String, result_column: &ResultColumn,
+                                                keys: &Vec<PkType>, callback: &mut F) -> Table {
+    // https://www.sqlite.org/lang_returning.html
+    let table = db.get_table(name);
+    let input_tables = vec![InputTable::new(table, None)];
+    let input_header = table.input_header();
+    let mut results_table = Table::new_result_table(generate_header(db, result_column, &input_tables));
+
+    for key in keys.iter() {
+        let row = table.input_row(db, *key, table.data.get(key).unwrap());
 
-    for expr in exprs.iter() {
-        if push_header {
-            match ExpressionEvaluator::new(row, &results_table.header).with_tables(input_tables.clone())
-                                                                      .with_column_def()
-                                                                      .eval_expr(expr) {
-                ExpressionResult::ColumnDef(def) => results_table.header.push(def.clone()),
-                _ => {}, // FIXME No idea
+        results_table.push_row(match *result_column {
+            ResultColumn::Expressions(ref exprs) => {
+                exprs.iter().map(|&(ref expr, _)| eval_row(db, None, &row, &input_header, &input_tables, expr))
+                            .collect()
             }
+            ResultColumn::Asterisk => row[..table.header.len()].to_vec(),
+        });
+    }
+
+    if !db.failed() {
+        for row in results_table.data.values() {
+            (*callback)(row, &results_table.header);
+        }
+    }
+
+    results_table
+}
+
+fn matching_keys(db: &Rusql, name: &String, expr: &Expression) -> Vec<PkType> {
+    let table = db.get_table(name);
+    let mut keys: Vec<PkType> = Vec::new();
+
+    for (key, row) in table.data.iter() {
+        let row = table.input_row(db, *key, row);
+
+        if ExpressionEvaluator::new(&row, &table.header).with_tables(vec![InputTable::new(table, None)])
+                                                       .with_db(db)
+                                                       .eval_bool(expr) {
+            keys.push(*key);
+        }
+    }
+
+    keys
+}
+
+// A subquery is given the evaluator of the enclosing query's row as `outer`.
+pub fn select<F: FnMut(&TableRow, &TableHeader)>(db: &Rusql, select_def: &SelectDef,
+                                                 outer: Option<&ExpressionEvaluator>, mut callback: F) -> Table {
+    // https://www.sqlite.org/lang_select.html#compound
+    let (limit, offset) = eval_limit(db, outer, select_def);
+
+    // Without ORDER BY, DISTINCT or compound operators, rows past LIMIT + OFFSET can
+    // never be returned.
+    let scan_limit = match (&select_def.ordering_terms, select_def.distinct, select_def.compound.len()) {
+        (&None, false, 0) => limit.and_then(|limit| limit.checked_add(offset)),
+        _ => None,
+    };
+
+    // The ORDER BY terms of a compound SELECT can only refer to its result columns.
+    let order_exprs: Vec<Expression> = match (&select_def.ordering_terms, select_def.compound.len()) {
+        (&Some(ref ordering_terms), 0) => ordering_terms.iter().map(|term| term.expr.clone()).collect(),
+        _ => Vec::new(),
+    };
+
+    let (header, mut rows) = select_core(db, outer, select_def, &order_exprs, scan_limit);
+
+    for &(operator, ref core) in select_def.compound.iter() {
+        let (core_header, core_rows) = select_core(db, outer, core, &Vec::new(), None);
+
+        if core_header.len() != header.len() {
+            println!("error: SELECTs to the left and right of {} do not have the same number of result columns",
+                     operator);
+            return Table::new_result_table(header);
         }
-        match ExpressionEvaluator::new(row, &results_table.header).with_tables(input_tables.clone())
-                                                                  .eval_expr(expr) {
-            ExpressionResult::Value(v) => new_row.push(v),
-            _ => {}, // FIXME No idea
+        rows = compound_rows(operator, rows, core_rows);
+    }
+
+    if let Some(ref ordering_terms) = select_def.ordering_terms {
+        debug!("ORDER BY");
+        let columns = match ordering_columns(ordering_terms, &header, order_exprs.len() > 0) {
+            Ok(columns) => columns,
+            Err(e) => {
+                println!("error: {}", e);
+                return Table::new_result_table(header);
+            }
+        };
+
+        let mut keyed_rows: Vec<(TableRow, TableRow)> = rows.into_iter().map(|row| {
+            (columns.iter().map(|&x| row[x].clone()).collect::<TableRow>(), row)
+        }).collect();
+
+        keyed_rows.as_mut_slice().sort_by(|a, b| cmp_ordering_values(&a.0, &b.0, ordering_terms));
+
+        rows = keyed_rows.into_iter().map(|(_, mut row)| {
+            row.truncate(header.len());
+            row
+        }).collect();
+    }
+
+    let mut results_table = Table::new_result_table(header);
+    let count = rows.len();
+
+    for row in rows.into_iter().skip(offset).take(limit.unwrap_or(count)) {
+        results_table.push_row(row);
+    }
+
+    // A statement that raised an error passes no rows on.
+    if !db.failed() {
+        for row in results_table.data.values() {
+            callback(row, &results_table.header);
         }
     }
 
-    results_table.push_row(new_row);
+    results_table
+}
+
+// Each result row is followed by its values of `order_exprs`.
+fn select_core(db: &Rusql, outer: Option<&ExpressionEvaluator>, select_def: &SelectDef,
+               order_exprs: &Vec<Expression>, scan_limit: Option<usize>) -> (TableHeader, Vec<TableRow>) {
+    let subqueries = generate_subqueries(db, outer, select_def);
+    let mut input_tables: Vec<InputTable> = Vec::new();
+    // Without grouping or window functions, each input row gives one result row.
+    let input_limit = match (&select_def.group_by, is_aggregate_query(select_def)) {
+        (&None, false) if window_functions(select_def, order_exprs).is_empty() => scan_limit,
+        _ => None,
+    };
+    let mut input_product = generate_inputs(db, outer, &subqueries, &mut input_tables, select_def, input_limit);
+
+    filter_inputs(db, outer, &mut input_product, &input_tables, select_def, input_limit);
+
+    let groups = group_inputs(db, outer, &input_product, &input_tables, select_def);
+    // Window functions are computed over the rows of the input product, so
+    // `prepare` rejects them in aggregate queries.
+    let windows = match groups {
+        Some(_) => BTreeMap::new(),
+        None => window_values(db, outer, &input_product, &input_tables, select_def, order_exprs),
+    };
+    let header = generate_header(db, &select_def.result_column, &input_tables);
+    let rows = generate_result_set(db, outer, input_product, groups, windows, &input_tables, select_def,
+                                   order_exprs, scan_limit);
+
+    (header, rows)
+}
+
+fn compound_rows(operator: CompoundOperator, left: Vec<TableRow>, right: Vec<TableRow>) -> Vec<TableRow> {
+    match operator {
+        CompoundOperator::UnionAll => {
+            let mut rows = left;
+            rows.extend(right.into_iter());
+            rows
+        }
+        CompoundOperator::Union => {
+            let mut rows = left;
+            rows.extend(right.into_iter());
+            distinct_rows(rows)
+        }
+        CompoundOperator::Intersect | CompoundOperator::Except => {
+            let right: BTreeSet<OrderedRow> = right.into_iter().map(|row| OrderedRow(row)).collect();
+            let intersect = operator == CompoundOperator::Intersect;
+
+            distinct_rows(left).into_iter().filter(|row| {
+                right.contains(&OrderedRow(row.clone())) == intersect
+            }).collect()
+        }
+    }
+}
+
+// NULLs are not distinct from each other here, unlike in comparisons.
+fn distinct_rows(rows: Vec<TableRow>) -> Vec<TableRow> {
+    let mut seen: BTreeSet<OrderedRow> = BTreeSet::new();
+    let mut distinct_rows: Vec<TableRow> = Vec::new();
+
+    for row in rows.into_iter() {
+        if seen.insert(OrderedRow(row.clone())) {
+            distinct_rows.push(row);
+        }
+    }
+
+    distinct_rows
+}
+
+fn natural_columns(tables: &Vec<InputTable>) -> Vec<String> {
+    let right = tables.last().unwrap();
+
+    right.header.iter().filter(|col| {
+        tables.iter().take(tables.len() - 1).any(|left| left.get_column_def_by_name(&col.name).is_some())
+    }).map(|col| col.name.clone()).collect()
+}
+
+// Range variables are told apart by position, not by comparing the tables, so
+// that a table can be joined with itself.
+fn using_join(tables: &Vec<InputTable>, columns: &Vec<String>,
+              constraints: &mut Vec<Expression>) -> Result<(), String> {
+    let right = tables.last().unwrap();
+
+    for column_name in columns.iter() {
+        let left = tables.iter().take(tables.len() - 1).find(|left| {
+            left.get_column_def_by_name(column_name).is_some()
+        });
+
+        match left {
+            Some(left) if right.get_column_def_by_name(column_name).is_some() => {
+                let left_column = Expression::TableName((left.name.clone(), box Expression::ColumnName(column_name.clone())));
+                let right_column = Expression::TableName((right.name.clone(),
+                                                          box Expression::ColumnName(column_name.clone())));
+
+                constraints.push(Expression::BinaryOperator((BinaryOperator::Equals, box left_column, box right_column)));
+            }
+            _ => {
+                return Err(format!("cannot join using column {} - column not present in both tables", column_name));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn generate_subqueries(db: &Rusql, outer: Option<&ExpressionEvaluator>, select_def: &SelectDef) -> Vec<Table> {
+    let mut subqueries: Vec<Table> = Vec::new();
+
+    for table_or_subquery in from_sources(select_def).into_iter() {
+        if let &TableOrSubquery::Subquery((ref select_def, _)) = table_or_subquery {
+            subqueries.push(select(db, &**select_def, outer, |_,_| {}));
+        }
+    }
+
+    subqueries
+}
+
+fn from_sources(select_def: &SelectDef) -> Vec<&TableOrSubquery> {
+    let mut sources: Vec<&TableOrSubquery> = Vec::new();
+
+    match select_def.from_clause {
+        Some(FromClause::TableOrSubquery(ref table_or_subquery)) => {
+            sources.extend(table_or_subquery.iter());
+        }
+        Some(FromClause::JoinClause(ref table_or_subquery, ref join_clauses)) => {
+            sources.push(table_or_subquery);
+
+            if let &Some(ref join_clauses) = join_clauses {
+                sources.extend(join_clauses.iter().map(|&(_, ref table_or_subquery, _)| table_or_subquery));
+            }
+        }
+        None => {}
+    }
+
+    sources
+}
+
+fn input_table<'a, I: Iterator<Item=&'a Table>>(db: &'a Rusql, subqueries: &mut I,
+                                                table_or_subquery: &TableOrSubquery) -> InputTable<'a> {
+    match table_or_subquery {
+        &TableOrSubquery::Table((ref name, ref alias)) => InputTable::new(db.get_table(name), alias.as_ref()),
+        &TableOrSubquery::Subquery((_, ref alias)) => InputTable::new(subqueries.next().unwrap(), alias.as_ref()),
+    }
+}
+
+fn generate_inputs<'a>(db: &'a Rusql, outer: Option<&ExpressionEvaluator>, subqueries: &'a Vec<Table>,
+                       input_tables: &mut Vec<InputTable<'a>>, select_def: &SelectDef,
+                       scan_limit: Option<usize>) -> Table {
+    // https://www.sqlite.org/lang_select.html#fromclause
+    let mut input_header: TableHeader = Vec::new();
+    // Without a FROM clause the input is a single row with no columns.
+    let mut rows: Vec<TableRow> = vec![Vec::new()];
+
+    if let Some(ref from_clause) = select_def.from_clause {
+        let mut joins: Vec<(JoinOperator, &TableOrSubquery, Option<&JoinConstraint>)> = Vec::new();
+
+        match from_clause {
+            &FromClause::TableOrSubquery(ref table_or_subquery) => {
+                // A comma separated list of tables is a chain of cross joins.
+                for table_or_subquery in table_or_subquery.iter() {
+                    joins.push((JoinOperator::Join(JoinType::Cross), table_or_subquery, None));
+                }
+            },
+            &FromClause::JoinClause(ref table_or_subquery, ref join_clauses) => {
+                joins.push((JoinOperator::Join(JoinType::Cross), table_or_subquery, None));
+
+                if let &Some(ref join_clauses) = join_clauses {
+                    for &(join_operator, ref table_or_subquery, ref join_const) in join_clauses.iter() {
+                        joins.push((join_operator, table_or_subquery, join_const.as_ref()));
+                    }
+                }
+            },
+        }
+
+        let mut subqueries = subqueries.iter();
+        let count = joins.len();
+
+        for (i, (join_operator, table_or_subquery, join_const)) in joins.into_iter().enumerate() {
+            let table = input_table(db, &mut subqueries, table_or_subquery);
+            let mut constraints: Vec<Expression> = Vec::new();
+            input_header.push_all(table.input_header().as_slice());
+            input_tables.push(table);
+
+            if let Some(&JoinConstraint::On(ref expr)) = join_const {
+                constraints.push(expr.clone());
+            }
+
+            // The columns of a NATURAL or USING join are merged into one, which
+            // unqualified names and SELECT * see.
+            let merged = match (join_operator, join_const) {
+                (JoinOperator::Natural(_), _) => natural_columns(input_tables),
+                (_, Some(&JoinConstraint::Using(ref columns))) => columns.clone(),
+                _ => Vec::new(),
+            };
+            if let Err(e) = using_join(input_tables, &merged, &mut constraints) {
+                println!("error: {}", e);
+                return Table::new_result_table(input_header);
+            }
+            input_tables.last_mut().unwrap().merged = merged;
+
+            let join_type = match join_operator {
+                JoinOperator::Join(join_type) | JoinOperator::Natural(join_type) => join_type,
+            };
+
+            // Later joins and the WHERE clause may drop rows, so only the last join
+            // can stop early.
+            let limit = if i + 1 == count && select_def.where_expr.is_none() { scan_limit } else { None };
+            rows = join(db, outer, rows, &input_header, input_tables, join_type, &constraints, limit);
+        }
+    }
+
+    let mut input_product = Table::new_result_table(input_header);
+
+    for row in rows.into_iter() {
+        input_product.push_row(row);
+    }
+
+    input_product
+}
+
+// The constraints are applied while joining, so that the NULL padding of outer
+// joins sees them.
+fn join(db: &Rusql, outer: Option<&ExpressionEvaluator>, rows: Vec<TableRow>, header: &TableHeader,
+        tables: &Vec<InputTable>, join_type: JoinType, constraints: &Vec<Expression>,
+        limit: Option<usize>) -> Vec<TableRow> {
+    // https://www.sqlite.org/lang_select.html#fromclause
+    let table = tables.last().unwrap();
+    let left_width = header.len() - table.input_width();
+    let right_rows: Vec<TableRow> = table.data.iter().map(|(key, row)| table.input_row(db, *key, row)).collect();
+    let mut right_matched: Vec<bool> = repeat(false).take(right_rows.len()).collect();
+    let mut joined_rows: Vec<TableRow> = Vec::new();
+
+    for left_row in rows.into_iter() {
+        let mut matched = false;
+
+        for (i, right_row) in right_rows.iter().enumerate() {
+            if Some(joined_rows.len()) == limit {
+                return joined_rows;
+            }
+
+            let mut new_row = left_row.clone();
+            new_row.push_all(right_row.as_slice());
+
+            if constraints.iter().all(|expr| {
+                ExpressionEvaluator::new(&new_row, header).with_tables(tables.clone())
+                                                          .with_db(db)
+                                                          .with_outer(outer)
+                                                          .eval_bool(expr)
+            }) {
+                matched = true;
+                right_matched[i] = true;
+                joined_rows.push(new_row);
+            }
+        }
+
+        if !matched && (join_type == JoinType::Left || join_type == JoinType::Full) {
+            let mut new_row = left_row;
+            new_row.extend(repeat(LiteralValue::Null).take(table.input_width()));
+            joined_rows.push(new_row);
+        }
+    }
+
+    if join_type == JoinType::Right || join_type == JoinType::Full {
+        for (right_row, matched) in right_rows.into_iter().zip(right_matched.into_iter()) {
+            if Some(joined_rows.len()) == limit {
+                break;
+            }
+
+            if !matched {
+                let mut new_row: TableRow = repeat(LiteralValue::Null).take(left_width).collect();
+                new_row.push_all(right_row.as_slice());
+                joined_rows.push(new_row);
+            }
+        }
+    }
+
+    joined_rows
+}
+
+fn filter_inputs(db: &Rusql, outer: Option<&ExpressionEvaluator>, input_product: &mut Table,
+                 input_tables: &Vec<InputTable>, select_def: &SelectDef, scan_limit: Option<usize>) {
+    // https://www.sqlite.org/lang_select.html#whereclause
+
+    if let Some(ref expr) = select_def.where_expr {
+        let mut keys: Vec<PkType> = Vec::new();
+        let mut kept = 0us;
+
+        for (key, row) in input_product.data.iter() {
+            if Some(kept) == scan_limit || !ExpressionEvaluator::new(row, &input_product.header)
+                                                                  .with_tables(input_tables.clone())
+                                                                  .with_db(db)
+                                                                  .with_outer(outer)
+                                                                  .eval_bool(expr) {
+                keys.push(*key);
+            } else {
+                kept += 1;
+            }
+        }
+
+        for key in keys.iter() {
+            input_product.data.remove(key);
+        }
+    }
+}
+
+fn is_aggregate_query(select_def: &SelectDef) -> bool {
+    let in_results = match select_def.result_column {
+        ResultColumn::Expressions(ref exprs) => exprs.iter().any(|&(ref expr, _)| expr.contains_aggregate()),
+        ResultColumn::Asterisk => false,
+    };
+
+    in_results || select_def.having.is_some()
+}
+
+fn group_inputs(db: &Rusql, outer: Option<&ExpressionEvaluator>, input_product: &Table, input_tables: &Vec<InputTable>,
+                select_def: &SelectDef) -> Option<Vec<Vec<PkType>>> {
+    // https://www.sqlite.org/lang_select.html#resultset
+    match select_def.group_by {
+        Some(ref exprs) => {
+            // Sort the rows by their GROUP BY values, then split them into runs of
+            // equal values. NULLs compare equal here, so they form one group.
+            let mut keyed_rows: Vec<(TableRow, PkType)> = input_product.data.iter().map(|(key, row)| {
+                let values: TableRow = exprs.iter().map(|expr| {
+                    eval_row(db, outer, row, &input_product.header, input_tables, expr)
+                }).collect();
+                (values, *key)
+            }).collect();
+
+            keyed_rows.as_mut_slice().sort_by(|a, b| cmp_rows(&a.0, &b.0));
+
+            let mut groups: Vec<Vec<PkType>> = Vec::new();
+            let mut last_values: Option<TableRow> = None;
+
+            for (values, key) in keyed_rows.into_iter() {
+                let same_group = match last_values {
+                    Some(ref last_values) => cmp_rows(last_values, &values) == Ordering::Equal,
+                    None => false,
+                };
+
+                if same_group {
+                    groups.last_mut().unwrap().push(key);
+                } else {
+                    groups.push(vec![key]);
+                }
+                last_values = Some(values);
+            }
+
+            Some(groups)
+        }
+        // Without GROUP BY, an aggregate query forms a single group.
+        None if is_aggregate_query(select_def) => Some(vec![input_product.data.keys().map(|key| *key).collect()]),
+        None => None,
+    }
+}
+
+fn generate_result_set(db: &Rusql, outer: Option<&ExpressionEvaluator>, input_product: Table,
+                       groups: Option<Vec<Vec<PkType>>>, windows: BTreeMap<PkType, TableRow>,
+                       input_tables: &Vec<InputTable>, select_def: &SelectDef,
+                       order_exprs: &Vec<Expression>, scan_limit: Option<usize>) -> Vec<TableRow> {
+    // https://www.sqlite.org/lang_select.html#resultset
+    let mut rows: Vec<TableRow> = Vec::new();
+    let mut order_values: Vec<TableRow> = Vec::new();
+
+    if let Some(ref groups) = groups {
+        for keys in groups.iter() {
+            let group: Vec<&TableRow> = keys.iter().map(|key| input_product.data.get(key).unwrap()).collect();
+
+            if let Some((row, values)) = generate_aggregate_row(db, outer, &group, &input_product.header, select_def,
+                                                                order_exprs, input_tables) {
+                rows.push(row);
+                order_values.push(values);
+            }
+        }
+    } else {
+        let calls = window_functions(select_def, order_exprs);
+
+        for (key, row) in input_product.data.iter() {
+            if Some(rows.len()) == scan_limit {
+                break;
+            }
+
+            match select_def.result_column {
+                ResultColumn::Expressions(ref exprs) => {
+                    let exprs: Vec<Expression> = exprs.iter().map(|&(ref expr, _)| {
+                        match windows.get(key) {
+                            Some(values) => fold_windows(expr, &calls, values),
+                            None => expr.clone(),
+                        }
+                    }).collect();
+                    rows.push(generate_row_from_expressions(db, outer, row, &input_product.header, &exprs, input_tables));
+                }
+                ResultColumn::Asterisk => rows.push(visible_columns(row, input_tables)),
+            }
+
+            let exprs: Vec<Expression> = order_exprs.iter().map(|expr| {
+                match windows.get(key) {
+                    Some(values) => fold_windows(expr, &calls, values),
+                    None => expr.clone(),
+                }
+            }).collect();
+            order_values.push(generate_row_from_expressions(db, outer, row, &input_product.header, &exprs, input_tables));
+        }
+    }
+
+    // DISTINCT tells rows apart by their result columns alone.
+    let mut distinct: BTreeSet<OrderedRow> = BTreeSet::new();
+    let mut result_rows: Vec<TableRow> = Vec::new();
+
+    for (mut row, values) in rows.into_iter().zip(order_values.into_iter()) {
+        if select_def.distinct && !distinct.insert(OrderedRow(row.clone())) {
+            continue;
+        }
+
+        row.extend(values.into_iter());
+        result_rows.push(row);
+    }
+
+    result_rows
+}
+
+// A negative LIMIT means no limit.
+fn eval_limit(db: &Rusql, outer: Option<&ExpressionEvaluator>, select_def: &SelectDef) -> (Option<usize>, usize) {
+    // https://www.sqlite.org/lang_select.html#limitoffset
+    let empty_row: TableRow = Vec::new();
+    let empty_header: TableHeader = Vec::new();
+
+    let limit = match select_def.limit {
+        Some(ref expr) => eval_row(db, outer, &empty_row, &empty_header, &Vec::new(), expr).to_int(),
+        None => -1,
+    };
+    let offset = match select_def.offset {
+        Some(ref expr) => eval_row(db, outer, &empty_row, &empty_header, &Vec::new(), expr).to_int(),
+        None => 0,
+    };
+
+    (if limit < 0 { None } else { Some(limit as usize) }, max(offset, 0) as usize)
+}
+
+// A term names a result column by number or name, or else sorts by its own value,
+// which `select_core` appended after the result columns if `computed`.
+fn ordering_columns(ordering_terms: &Vec<OrderingTerm>, header: &TableHeader,
+                    computed: bool) -> Result<Vec<usize>, String> {
+    let mut columns: Vec<usize> = Vec::new();
+
+    for (i, term) in ordering_terms.iter().enumerate() {
+        let column = match term.expr {
+            Expression::LiteralValue(LiteralValue::Integer(n)) => {
+                if n < 1 || n as usize > header.len() {
+                    return Err(format!("ORDER BY term {} out of range - should be between 1 and {}",
+                                       i + 1, header.len()));
+                }
+                n as usize - 1
+            }
+            Expression::ColumnName(ref name) if header.iter().any(|col| &col.name == name) => {
+                header.iter().position(|col| &col.name == name).unwrap()
+            }
+            _ if computed => header.len() + i,
+            _ => return Err(format!("ORDER BY term {} does not match any column in the result set", i + 1)),
+        };
+        columns.push(column);
+    }
+
+    Ok(columns)
+}
+
+fn generate_header(db: &Rusql, result_column: &ResultColumn, input_tables: &Vec<InputTable>) -> TableHeader {
+    let mut header: TableHeader = Vec::new();
+
+    match result_column {
+        &ResultColumn::Expressions(ref exprs) => {
+            let empty_row: TableRow = Vec::new();
+            let empty_header: TableHeader = Vec::new();
+
+            for &(ref expr, ref alias) in exprs.iter() {
+                let mut def = match ExpressionEvaluator::new(&empty_row, &empty_header).with_tables(input_tables.clone())
+                                                                                       .with_db(db)
+                                                                                       .with_column_def()
+                                                                                       .eval_expr(expr) {
+                    ExpressionResult::ColumnDef(def) => def,
+                    _ => ColumnDef {
+                        name: format!("{}", expr),
+                        column_type: None,
+                        column_constraints: Vec::new(),
+                    },
+                };
+
+                if let &Some(ref alias) = alias {
+                    def.name = alias.clone();
+                }
+                // A result column keeps only the name and type of its source, so
+                // that a result table used as a subquery computes nothing itself.
+                def.column_constraints = Vec::new();
+                header.push(def);
+            }
+        }
+        &ResultColumn::Asterisk => {
+            for table in input_tables.iter() {
+                header.extend(table.header.iter().filter(|col| !table.is_merged(&col.name)).map(|col| {
+                    ColumnDef {
+                        name: col.name.clone(),
+                        column_type: col.column_type,
+                        column_constraints: Vec::new(),
+                    }
+                }));
+            }
+        }
+    }
+
+    header
+}
+
+fn visible_columns(row: &TableRow, input_tables: &Vec<InputTable>) -> TableRow {
+    let mut new_row: TableRow = Vec::new();
+    let mut offset = 0us;
+
+    for (i, table) in input_tables.iter().enumerate() {
+        for (x, column) in table.header.iter().enumerate() {
+            if table.is_merged(&column.name) {
+                continue;
+            }
+
+            // A merged column shows the first non-NULL value among the range
+            // variables sharing it, which matters for RIGHT and FULL joins.
+            let mut value = row[offset + x].clone();
+            let mut later_offset = offset + table.input_width();
+
+            for later in input_tables.iter().skip(i + 1) {
+                if value == LiteralValue::Null && later.is_merged(&column.name) {
+                    value = row[later_offset + later.get_input_column_index(&column.name).unwrap()].clone();
+                }
+                later_offset += later.input_width();
+            }
+            new_row.push(value);
+        }
+        offset += table.input_width();
+    }
+
+    new_row
+}
+
+fn generate_row_from_expressions(db: &Rusql, outer: Option<&ExpressionEvaluator>, row: &TableRow, header: &TableHeader,
+                                 exprs: &Vec<Expression>, input_tables: &Vec<InputTable>) -> TableRow {
+    exprs.iter().map(|expr| eval_row(db, outer, row, header, input_tables, expr)).collect()
+}
+
+fn eval_row(db: &Rusql, outer: Option<&ExpressionEvaluator>, row: &TableRow, header: &TableHeader,
+            input_tables: &Vec<InputTable>, expr: &Expression) -> LiteralValue {
+    result_to_literal(ExpressionEvaluator::new(row, header).with_tables(input_tables.clone())
+                                                           .with_db(db)
+                                                           .with_outer(outer)
+                                                           .eval_expr(expr))
+}
+
+fn generate_aggregate_row(db: &Rusql, outer: Option<&ExpressionEvaluator>, group: &Vec<&TableRow>,
+                          header: &TableHeader, select_def: &SelectDef, order_exprs: &Vec<Expression>,
+                          input_tables: &Vec<InputTable>) -> Option<(TableRow, TableRow)> {
+    // Bare columns take their values from the last row of the group, like SQLite.
+    let row: TableRow = match group.last() {
+        Some(row) => (*row).clone(),
+        None => repeat(LiteralValue::Null).take(header.len()).collect(),
+    };
+
+    if let Some(ref expr) = select_def.having {
+        let expr = fold_aggregates(db, outer, expr, group, header, input_tables);
+
+        if !ExpressionEvaluator::new(&row, header).with_tables(input_tables.clone())
+                                                  .with_db(db)
+                                                  .with_outer(outer)
+                                                  .eval_bool(&expr) {
+            return None;
+        }
+    }
+
+    let result_row = match select_def.result_column {
+        ResultColumn::Expressions(ref exprs) => {
+            let exprs: Vec<Expression> = exprs.iter().map(|&(ref expr, _)| {
+                fold_aggregates(db, outer, expr, group, header, input_tables)
+            }).collect();
+
+            generate_row_from_expressions(db, outer, &row, header, &exprs, input_tables)
+        }
+        ResultColumn::Asterisk => visible_columns(&row, input_tables),
+    };
+
+    let order_exprs: Vec<Expression> = order_exprs.iter().map(|expr| {
+        fold_aggregates(db, outer, expr, group, header, input_tables)
+    }).collect();
+
+    Some((result_row, generate_row_from_expressions(db, outer, &row, header, &order_exprs, input_tables)))
+}
+
+fn fold_aggregates(db: &Rusql, outer: Option<&ExpressionEvaluator>, expr: &Expression, group: &Vec<&TableRow>,
+                   header: &TableHeader, input_tables: &Vec<InputTable>) -> Expression {
+    match expr {
+        &Expression::Function(ref call) if call.is_aggregate() => {
+            let mut values: Vec<LiteralValue> = Vec::new();
+            let mut separator = LiteralValue::Null;
+
+            for row in group.iter() {
+                if call.args.len() == 0 {
+                    values.push(LiteralValue::Integer(1));
+                } else {
+                    values.push(eval_row(db, outer, *row, header, input_tables, &call.args[0]));
+                }
+            }
+
+            if let (Some(row), Some(expr)) = (group.first(), call.args.get(1)) {
+                separator = eval_row(db, outer, *row, header, input_tables, expr);
+            }
+
+            match eval_aggregate(call, values, separator) {
+                Ok(value) => Expression::LiteralValue(value),
+                Err(e) => {
+                    db.raise_error(e);
+                    Expression::LiteralValue(LiteralValue::Null)
+                }
+            }
+        }
+        &Expression::Function(ref call) => {
+            Expression::Function(FunctionCall {
+                args: call.args.iter().map(|arg| fold_aggregates(db, outer, arg, group, header, input_tables)).collect(),
+                ..call.clone()
+            })
+        }
+        &Expression::BinaryOperator((b, ref left, ref right)) => {
+            Expression::BinaryOperator((b, box fold_aggregates(db, outer, &**left, group, header, input_tables),
+                                           box fold_aggregates(db, outer, &**right, group, header, input_tables)))
+        }
+        &Expression::UnaryOperator((u, ref expr)) => {
+            Expression::UnaryOperator((u, box fold_aggregates(db, outer, &**expr, group, header, input_tables)))
+        }
+        &Expression::Cast((ref expr, column_type)) => {
+            Expression::Cast((box fold_aggregates(db, outer, &**expr, group, header, input_tables), column_type))
+        }
+        &Expression::InSelect((ref expr, ref select_def, negated)) => {
+            Expression::InSelect((box fold_aggregates(db, outer, &**expr, group, header, input_tables),
+                                  select_def.clone(), negated))
+        }
+        &Expression::List(ref exprs) => {
+            Expression::List(exprs.iter().map(|expr| fold_aggregates(db, outer, expr, group, header, input_tables)).collect())
+        }
+        &Expression::Case((ref base, ref branches, ref else_expr)) => {
+            let branches = branches.iter().map(|&(ref when, ref then)| {
+                (fold_aggregates(db, outer, when, group, header, input_tables),
+                 fold_aggregates(db, outer, then, group, header, input_tables))
+            }).collect();
+
+            let base = base.as_ref().map(|expr| box fold_aggregates(db, outer, &**expr, group, header, input_tables));
+            let else_expr = else_expr.as_ref().map(|expr| {
+                box fold_aggregates(db, outer, &**expr, group, header, input_tables)
+            });
+
+            Expression::Case((base, branches, else_expr))
+        }
+        _ => expr.clone(),
+    }
+}
+
+struct Partition<'a> {
+    keys: Vec<PkType>,
+    rows: Vec<&'a TableRow>,
+    order_values: Vec<TableRow>,
+    // The first and last of each row's peers.
+    peers: Vec<(usize, usize)>,
+}
+
+fn window_functions<'a>(select_def: &'a SelectDef,
+                        order_exprs: &'a Vec<Expression>) -> Vec<(&'a FunctionCall, &'a Over)> {
+    let mut calls: Vec<(&FunctionCall, &Over)> = Vec::new();
+
+    if let ResultColumn::Expressions(ref exprs) = select_def.result_column {
+        for &(ref expr, _) in exprs.iter() {
+            collect_window_functions(expr, &mut calls);
+        }
+    }
+
+    for expr in order_exprs.iter() {
+        collect_window_functions(expr, &mut calls);
+    }
+
+    calls
+}
+
+fn collect_window_functions<'a>(expr: &'a Expression, calls: &mut Vec<(&'a FunctionCall, &'a Over)>) {
+    match expr {
+        &Expression::WindowFunction((ref call, ref over)) => {
+            if !calls.iter().any(|&(other_call, other_over)| other_call == call && other_over == over) {
+                calls.push((call, over));
+            }
+        }
+        &Expression::Function(ref call) => {
+            for arg in call.args.iter() {
+                collect_window_functions(arg, calls);
+            }
+        }
+        &Expression::BinaryOperator((_, ref left, ref right)) => {
+            collect_window_functions(&**left, calls);
+            collect_window_functions(&**right, calls);
+        }
+        &Expression::UnaryOperator((_, ref expr)) => collect_window_functions(&**expr, calls),
+        &Expression::Cast((ref expr, _)) => collect_window_functions(&**expr, calls),
+        &Expression::InSelect((ref expr, _, _)) => collect_window_functions(&**expr, calls),
+        &Expression::List(ref exprs) => {
+            for expr in exprs.iter() {
+                collect_window_functions(expr, calls);
+            }
+        }
+        &Expression::Case((ref base, ref branches, ref else_expr)) => {
+            for expr in base.iter().chain(else_expr.iter()) {
+                collect_window_functions(&**expr, calls);
+            }
+            for &(ref when, ref then) in branches.iter() {
+                collect_window_functions(when, calls);
+                collect_window_functions(then, calls);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn window_values(db: &Rusql, outer: Option<&ExpressionEvaluator>, input_product: &Table,
+                 input_tables: &Vec<InputTable>, select_def: &SelectDef,
+                 order_exprs: &Vec<Expression>) -> BTreeMap<PkType, TableRow> {
+    // https://www.sqlite.org/windowfunctions.html
+    let calls = window_functions(select_def, order_exprs);
+    let mut values: BTreeMap<PkType, TableRow> = BTreeMap::new();
+
+    if calls.len() == 0 {
+        return values;
+    }
+
+    for key in input_product.data.keys() {
+        values.insert(*key, Vec::new());
+    }
+
+    for &(call, over) in calls.iter() {
+        let window = match resolve_window(over, select_def.windows.as_slice()) {
+            Ok(window) => window,
+            Err(e) => {
+                println!("error: {}", e);
+                for row in values.values_mut() {
+                    row.push(LiteralValue::Null);
+                }
+                continue;
+            }
+        };
+
+        for partition in partition_inputs(db, outer, input_product, input_tables, &window).iter() {
+            for (i, key) in partition.keys.iter().enumerate() {
+                let value = eval_window_function(db, outer, call, &window, partition, i,
+                                                 &input_product.header, input_tables);
+                values.get_mut(key).unwrap().push(value);
+            }
+        }
+    }
+
+    values
+}
+
+// A named window can only extend those named before it.
+fn resolve_window(over: &Over, windows: &[NamedWindow]) -> Result<WindowDef, String> {
+    let (window, windows) = match *over {
+        Over::Window(ref window) => (window.clone(), windows),
+        Over::Name(ref name) => match windows.iter().position(|&(ref window_name, _)| window_name == name) {
+            Some(x) => (windows[x].1.clone(), &windows[..x]),
+            None => return Err(format!("no such window: {}", name)),
+        },
+    };
+
+    match window.base.clone() {
+        Some(name) => {
+            let base = try!(resolve_window(&Over::Name(name), windows));
+
+            Ok(WindowDef {
+                base: None,
+                partition_by: base.partition_by,
+                ordering_terms: window.ordering_terms.or(base.ordering_terms),
+                frame: window.frame,
+            })
+        }
+        None => Ok(window),
+    }
+}
+
+fn partition_inputs<'a>(db: &Rusql, outer: Option<&ExpressionEvaluator>, input_product: &'a Table,
+                        input_tables: &Vec<InputTable>, window: &WindowDef) -> Vec<Partition<'a>> {
+    let no_exprs: Vec<Expression> = Vec::new();
+    let no_terms: Vec<OrderingTerm> = Vec::new();
+    let partition_by = window.partition_by.as_ref().unwrap_or(&no_exprs);
+    let ordering_terms = window.ordering_terms.as_ref().unwrap_or(&no_terms);
+
+    let mut keyed_rows: Vec<(TableRow, TableRow, PkType)> = input_product.data.iter().map(|(key, row)| {
+        let partition_values: TableRow = partition_by.iter().map(|expr| {
+            eval_row(db, outer, row, &input_product.header, input_tables, expr)
+        }).collect();
+        let order_values: TableRow = ordering_terms.iter().map(|term| {
+            eval_row(db, outer, row, &input_product.header, input_tables, &term.expr)
+        }).collect();
+        (partition_values, order_values, *key)
+    }).collect();
+
+    keyed_rows.as_mut_slice().sort_by(|a, b| {
+        match cmp_rows(&a.0, &b.0) {
+            Ordering::Equal => cmp_ordering_values(&a.1, &b.1, ordering_terms),
+            ordering => ordering,
+        }
+    });
+
+    let mut partitions: Vec<Partition> = Vec::new();
+    let mut last_values: Option<TableRow> = None;
+
+    for (partition_values, order_values, key) in keyed_rows.into_iter() {
+        let same_partition = match last_values {
+            Some(ref last_values) => cmp_rows(last_values, &partition_values) == Ordering::Equal,
+            None => false,
+        };
+
+        if !same_partition {
+            partitions.push(Partition {
+                keys: Vec::new(),
+                rows: Vec::new(),
+                order_values: Vec::new(),
+                peers: Vec::new(),
+            });
+        }
+
+        let partition = partitions.last_mut().unwrap();
+        let i = partition.keys.len();
+        let first_peer = match partition.order_values.last() {
+            Some(last_values) if cmp_rows(last_values, &order_values) == Ordering::Equal => partition.peers[i - 1].0,
+            _ => i,
+        };
+
+        partition.keys.push(key);
+        partition.rows.push(input_product.data.get(&key).unwrap());
+        partition.order_values.push(order_values);
+        partition.peers.push((first_peer, i));
+        last_values = Some(partition_values);
+    }
+
+    // Each row was given itself as its last peer; give it the last row it shares
+    // its first peer with.
+    for partition in partitions.iter_mut() {
+        let mut next: Option<(usize, usize)> = None;
+
+        for peers in partition.peers.iter_mut().rev() {
+            if let Some((first_peer, last_peer)) = next {
+                if first_peer == peers.0 {
+                    peers.1 = last_peer;
+                }
+            }
+            next = Some(*peers);
+        }
+    }
+
+    partitions
+}
+
+fn cmp_ordering_values(a: &TableRow, b: &TableRow, ordering_terms: &Vec<OrderingTerm>) -> Ordering {
+    for ((x, y), term) in a.iter().zip(b.iter()).zip(ordering_terms.iter()) {
+        let nulls_first = term.nulls == Nulls::First;
+
+        let ordering = match (x, y) {
+            (&LiteralValue::Null, &LiteralValue::Null) => Ordering::Equal,
+            (&LiteralValue::Null, _) => if nulls_first { Ordering::Less } else { Ordering::Greater },
+            (_, &LiteralValue::Null) => if nulls_first { Ordering::Greater } else { Ordering::Less },
+            _ => match term.order {
+                Order::Ascending => x.sort_cmp(y),
+                Order::Descending => y.sort_cmp(x),
+            },
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+fn eval_window_function(db: &Rusql, outer: Option<&ExpressionEvaluator>, call: &FunctionCall, window: &WindowDef,
+                        partition: &Partition, i: usize, header: &TableHeader,
+                        input_tables: &Vec<InputTable>) -> LiteralValue {
+    let count = partition.rows.len();
+    let (first_peer, _) = partition.peers[i];
+
+    match call.lower_name().as_slice() {
+        "row_number" => LiteralValue::Integer(i as isize + 1),
+        "rank" => LiteralValue::Integer(first_peer as isize + 1),
+        "dense_rank" => {
+            // The peer groups before this row's are counted by their first rows.
+            let groups = partition.peers.iter().take(first_peer).enumerate()
+                                        .filter(|&(j, &(first, _))| j == first).count();
+            LiteralValue::Integer(groups as isize + 1)
+        }
+        "ntile" => {
+            let buckets = window_arg(db, outer, call, 0, partition.rows[i], header, input_tables).to_int();
+            if buckets <= 0 {
+                return LiteralValue::Null;
+            }
+
+            // The first `count % buckets` buckets take one row more than the rest.
+            let size = count / buckets as usize;
+            let larger = count % buckets as usize;
+            let bucket = if i < larger * (size + 1) {
+                i / (size + 1)
+            } else {
+                larger + (i - larger * (size + 1)) / size
+            };
+            LiteralValue::Integer(bucket as isize + 1)
+        }
+        name @ "lag" | name @ "lead" => {
+            let offset = match call.args.len() {
+                1 => 1,
+                _ => window_arg(db, outer, call, 1, partition.rows[i], header, input_tables).to_int(),
+            };
+            let j = if name == "lag" { i as isize - offset } else { i as isize + offset };
+
+            if j >= 0 && (j as usize) < count {
+                window_arg(db, outer, call, 0, partition.rows[j as usize], header, input_tables)
+            } else {
+                window_arg(db, outer, call, 2, partition.rows[i], header, input_tables)
+            }
+        }
+        name => {
+            let (start, end) = frame_range(db, outer, window, partition, i, header, input_tables);
+
+            match name {
+                "first_value" if start < end => {
+                    window_arg(db, outer, call, 0, partition.rows[start], header, input_tables)
+                }
+                "last_value" if start < end => {
+                    window_arg(db, outer, call, 0, partition.rows[end - 1], header, input_tables)
+                }
+                "first_value" | "last_value" => LiteralValue::Null,
+                _ => {
+                    let values: Vec<LiteralValue> = partition.rows[start..end].iter().map(|row| {
+                        match call.args.len() {
+                            0 => LiteralValue::Integer(1),
+                            _ => window_arg(db, outer, call, 0, *row, header, input_tables),
+                        }
+                    }).collect();
+                    let separator = window_arg(db, outer, call, 1, partition.rows[i], header, input_tables);
+
+                    match eval_aggregate(call, values, separator) {
+                        Ok(value) => value,
+                        Err(e) => {
+                            db.raise_error(e);
+                            LiteralValue::Null
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn window_arg(db: &Rusql, outer: Option<&ExpressionEvaluator>, call: &FunctionCall, x: usize, row: &TableRow,
+              header: &TableHeader, input_tables: &Vec<InputTable>) -> LiteralValue {
+    match call.args.get(x) {
+        Some(expr) => eval_row(db, outer, row, header, input_tables, expr),
+        None => LiteralValue::Null,
+    }
+}
+
+fn frame_range(db: &Rusql, outer: Option<&ExpressionEvaluator>, window: &WindowDef, partition: &Partition,
+               i: usize, header: &TableHeader, input_tables: &Vec<InputTable>) -> (usize, usize) {
+    // https://www.sqlite.org/windowfunctions.html#frame_specifications
+    let default_frame = FrameSpec {
+        units: FrameUnits::Range,
+        start: FrameBound::UnboundedPreceding,
+        end: FrameBound::CurrentRow,
+    };
+    let frame = window.frame.as_ref().unwrap_or(&default_frame);
+    let descending = match window.ordering_terms {
+        Some(ref terms) => terms.first().map_or(false, |term| term.order == Order::Descending),
+        None => false,
+    };
+
+    let start_offset = frame_offset(db, outer, &frame.start, partition.rows[i], header, input_tables);
+    let end_offset = frame_offset(db, outer, &frame.end, partition.rows[i], header, input_tables);
+    let start = frame_bound(&frame.start, frame.units, start_offset, true, partition, i, descending);
+    let end = frame_bound(&frame.end, frame.units, end_offset, false, partition, i, descending);
+
+    (start, max(start, end))
+}
+
+fn frame_offset(db: &Rusql, outer: Option<&ExpressionEvaluator>, bound: &FrameBound, row: &TableRow,
+                header: &TableHeader, input_tables: &Vec<InputTable>) -> isize {
+    match *bound {
+        FrameBound::Preceding(ref expr) => -eval_row(db, outer, row, header, input_tables, expr).to_int(),
+        FrameBound::Following(ref expr) => eval_row(db, outer, row, header, input_tables, expr).to_int(),
+        _ => 0,
+    }
+}
+
+// The end of a frame is the position after its last row.
+fn frame_bound(bound: &FrameBound, units: FrameUnits, offset: isize, is_start: bool, partition: &Partition,
+               i: usize, descending: bool) -> usize {
+    let count = partition.rows.len();
+    let (first_peer, last_peer) = partition.peers[i];
+    let peers_bound = if is_start { first_peer } else { last_peer + 1 };
+
+    match (bound, units) {
+        (&FrameBound::UnboundedPreceding, _) => 0,
+        (&FrameBound::UnboundedFollowing, _) => count,
+        (&FrameBound::CurrentRow, FrameUnits::Rows) => if is_start { i } else { i + 1 },
+        (&FrameBound::CurrentRow, FrameUnits::Range) => peers_bound,
+        (_, FrameUnits::Rows) => {
+            let position = i as isize + offset + if is_start { 0 } else { 1 };
+            min(max(position, 0), count as isize) as usize
+        }
+        (_, FrameUnits::Range) => {
+            // The bound is on the first ORDER BY value; rows where it is NULL are
+            // only in range of each other.
+            let value = match partition.order_values[i].first() {
+                Some(value) if *value != LiteralValue::Null => value.to_real(),
+                _ => return peers_bound,
+            };
+            let sign = if descending { -1.0 } else { 1.0 };
+            let mut position = if is_start { count } else { 0 };
+
+            for (j, values) in partition.order_values.iter().enumerate() {
+                if values[0] == LiteralValue::Null {
+                    continue;
+                }
+
+                let distance = (values[0].to_real() - value) * sign;
+
+                if is_start && distance >= offset as f64 {
+                    return j;
+                }
+                if !is_start && distance <= offset as f64 {
+                    position = j + 1;
+                }
+            }
+            position
+        }
+    }
+}
+
+fn fold_windows(expr: &Expression, calls: &Vec<(&FunctionCall, &Over)>, values: &TableRow) -> Expression {
+    match expr {
+        &Expression::WindowFunction((ref call, ref over)) => {
+            match calls.iter().position(|&(other_call, other_over)| other_call == call && other_over == over) {
+                Some(x) => Expression::LiteralValue(values[x].clone()),
+                None => Expression::Null,
+            }
+        }
+        &Expression::Function(ref call) => {
+            Expression::Function(FunctionCall {
+                args: call.args.iter().map(|arg| fold_windows(arg, calls, values)).collect(),
+                ..call.clone()
+            })
+        }
+        &Expression::BinaryOperator((b, ref left, ref right)) => {
+            Expression::BinaryOperator((b, box fold_windows(&**left, calls, values),
+                                           box fold_windows(&**right, calls, values)))
+        }
+        &Expression::UnaryOperator((u, ref expr)) => {
+            Expression::UnaryOperator((u, box fold_windows(&**expr, calls, values)))
+        }
+        &Expression::Cast((ref expr, column_type)) => {
+            Expression::Cast((box fold_windows(&**expr, calls, values), column_type))
+        }
+        &Expression::InSelect((ref expr, ref select_def, negated)) => {
+            Expression::InSelect((box fold_windows(&**expr, calls, values), select_def.clone(), negated))
+        }
+        &Expression::List(ref exprs) => {
+            Expression::List(exprs.iter().map(|expr| fold_windows(expr, calls, values)).collect())
+        }
+        &Expression::Case((ref base, ref branches, ref else_expr)) => {
+            let branches = branches.iter().map(|&(ref when, ref then)| {
+                (fold_windows(when, calls, values), fold_windows(then, calls, values))
+            }).collect();
+
+            let base = base.as_ref().map(|expr| box fold_windows(&**expr, calls, values));
+            let else_expr = else_expr.as_ref().map(|expr| box fold_windows(&**expr, calls, values));
+
+            Expression::Case((base, branches, else_expr))
+        }
+        _ => expr.clone(),
+    }
 }
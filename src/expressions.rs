@@ -1,7 +1,13 @@
-use definitions::{Expression, LiteralValue, BinaryOperator, UnaryOperator, ColumnDef};
-use table::{Table, TableRow, TableHeader, get_column};
+use definitions::{Expression, LiteralValue, BinaryOperator, UnaryOperator, ColumnDef, FunctionCall, SelectDef};
+use definitions::CaseBranch;
+use table::{Table, InputTable, TableRow, TableHeader, get_column};
+use exec::select;
+use functions::eval_scalar_function;
+use rusql::Rusql;
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::num::Int;
 
 #[derive(PartialEq, Clone)]
 pub enum ExpressionResult {
@@ -23,10 +29,15 @@ pub struct ExpressionEvaluator<'a, 'b> {
     // FIXME wtf am I doing?!?!?!
     row: &'a TableRow,
     head: &'a TableHeader,
-    tables: Option<Vec<&'b Table>>,
+    tables: Option<Vec<InputTable<'b>>>,
+    db: Option<&'b Rusql>,
+    // The evaluator of the enclosing query's row, for correlated subqueries.
+    outer: Option<&'b ExpressionEvaluator<'b, 'b>>,
     get_column_def: bool,
-    as_column_alias: bool,
-    order_pass: Cell<bool>,
+    // Set when a subquery looks up a column through this evaluator.
+    correlated: Cell<bool>,
+    // An error raised without a database to abort the statement of.
+    error: RefCell<Option<String>>,
 }
 
 impl<'a, 'b> ExpressionEvaluator<'a, 'b> {
@@ -35,74 +46,162 @@ impl<'a, 'b> ExpressionEvaluator<'a, 'b> {
             row: row,
             head: head,
             tables: None,
+            db: None,
+            outer: None,
             get_column_def: false,
-            as_column_alias: false,
-            order_pass: Cell::new(false),
+            correlated: Cell::new(false),
+            error: RefCell::new(None),
         }
     }
 
+    fn raise_error(&self, e: String) {
+        match self.db {
+            Some(db) => db.raise_error(e),
+            None => {
+                let mut error = self.error.borrow_mut();
+                if error.is_none() {
+                    *error = Some(e);
+                }
+            }
+        }
+    }
+
+    pub fn take_error(&self) -> Option<String> {
+        self.error.borrow_mut().take()
+    }
+
     pub fn with_column_def(&'a mut self) -> &mut ExpressionEvaluator<'a, 'b> {
         self.get_column_def = true;
         self
     }
 
-    pub fn with_tables(&'a mut self, tables: Vec<&'b Table>) -> &mut ExpressionEvaluator<'a, 'b> {
+    pub fn with_tables(&'a mut self, tables: Vec<InputTable<'b>>) -> &mut ExpressionEvaluator<'a, 'b> {
         self.tables = Some(tables);
         self
     }
 
-    pub fn as_column_alias(&'a mut self) -> &mut ExpressionEvaluator<'a, 'b> {
-        self.as_column_alias = true;
+    pub fn with_db(&'a mut self, db: &'b Rusql) -> &mut ExpressionEvaluator<'a, 'b> {
+        self.db = Some(db);
         self
     }
 
-    pub fn order_of_operations(&'a self, expr: &Expression) -> Expression {
-        let (b1, left1, right1) = expr.unwrap_binary_operator();
-        let (b2, left2, right2) = right1.unwrap_binary_operator();
+    pub fn with_outer(&'a mut self, outer: Option<&'b ExpressionEvaluator<'b, 'b>>) -> &mut ExpressionEvaluator<'a, 'b> {
+        self.outer = outer;
+        self
+    }
+
+    pub fn eval_expr(&'a self, expr: &Expression) -> ExpressionResult {
+        match expr {
+            &Expression::LiteralValue(ref value) => ExpressionResult::Value(value.clone()),
+            &Expression::TableName(..) | &Expression::ColumnName(..) => {
+                match self.eval_column_name(expr, None, None) {
+                    ExpressionResult::Null => self.eval_outer_column_name(expr),
+                    result => result,
+                }
+            }
+            &Expression::BinaryOperator((b, ref expr1, ref expr2)) => {
+                self.eval_binary_operator(b, &**expr1, &**expr2)
+            }
+            &Expression::UnaryOperator((u, ref exp)) => self.eval_unary_operator(u, &**exp),
+            // Function calls, subqueries, CASE and CAST expressions have no column def.
+            &Expression::Function(_) | &Expression::Subquery(_) | &Expression::Exists(_) | &Expression::InSelect(_)
+                | &Expression::Case(_) | &Expression::Cast(_) if self.get_column_def => ExpressionResult::Null,
+            &Expression::Function(ref call) => self.eval_function(call),
+            &Expression::Cast((ref expr, column_type)) => {
+                ExpressionResult::Value(result_to_literal(self.eval_expr(&**expr)).cast(column_type))
+            }
+            &Expression::Case((ref base, ref branches, ref else_expr)) => self.eval_case(base, branches, else_expr),
+            &Expression::Subquery(ref select_def) => {
+                ExpressionResult::Value(self.with_subquery(&**select_def, |results| {
+                    match results.data.values().next() {
+                        Some(row) if is_single_column(results) => row[0].clone(),
+                        _ => LiteralValue::Null,
+                    }
+                }))
+            }
+            &Expression::Exists(ref select_def) => {
+                ExpressionResult::Value(self.with_subquery(&**select_def, |results| {
+                    LiteralValue::Boolean(results.data.len() > 0)
+                }))
+            }
+            &Expression::InSelect((ref expr, ref select_def, negated)) => {
+                let value = result_to_literal(self.eval_expr(&**expr));
+
+                ExpressionResult::Value(self.with_subquery(&**select_def, |results| {
+                    if !is_single_column(results) {
+                        return LiteralValue::Null;
+                    }
+                    eval_in(&value, results.data.values().map(|row| &row[0]), negated)
+                }))
+            }
+            _ => ExpressionResult::Null,
+        }
+    }
+
+    // Only the WHEN expressions up to the first that matches are evaluated, and then
+    // only its THEN expression.
+    fn eval_case(&'a self, base: &Option<Box<Expression>>, branches: &Vec<CaseBranch>,
+                 else_expr: &Option<Box<Expression>>) -> ExpressionResult {
+        // https://www.sqlite.org/lang_expr.html#case
+        let base = base.as_ref().map(|expr| result_to_literal(self.eval_expr(&**expr)));
 
-        if b1.ord_val() < b2.ord_val() {
-            let right2 = self.order_of_operations(&right2);
+        for &(ref when, ref then) in branches.iter() {
+            let value = result_to_literal(self.eval_expr(when));
+            // A NULL base matches no WHEN, not even a NULL one.
+            let matched = match base {
+                Some(LiteralValue::Null) => false,
+                Some(ref base) => value == *base,
+                None => value.to_bool(),
+            };
 
-            let new_expr_child = Expression::BinaryOperator((b1, box left2, box left1));
-            let new_expr_parent = Expression::BinaryOperator((b2, box right2, box new_expr_child));
+            if matched {
+                return self.eval_expr(then);
+            }
+        }
 
-            return new_expr_parent;
-        } else if b1.ord_val() == b2.ord_val() && b1 > b2{
-            let right2 = self.order_of_operations(&right2);
+        match *else_expr {
+            Some(ref expr) => self.eval_expr(&**expr),
+            None => ExpressionResult::Value(LiteralValue::Null),
+        }
+    }
 
-            let new_expr_child = Expression::BinaryOperator((b1, box left1, box left2));
-            let new_expr_parent = Expression::BinaryOperator((b2, box right2, box new_expr_child));
+    // A subquery that looked up no column of the current row is uncorrelated, and
+    // its results are reused for the rest of the statement.
+    fn with_subquery<T, F: FnOnce(&Table) -> T>(&'a self, select_def: &SelectDef, f: F) -> T {
+        let db = match self.db {
+            Some(db) => db,
+            None => return f(&Table::new_result_table(Vec::new())),
+        };
 
-            return new_expr_parent;
-        } else if b2 != BinaryOperator::Null {
-            let right2 = self.order_of_operations(&right2);
+        {
+            let cache = db.subquery_cache.borrow();
 
-            let new_expr_child = Expression::BinaryOperator((b2, box left2, box right2));
-            let new_expr_parent = Expression::BinaryOperator((b1, box left1, box new_expr_child));
+            if let Some(&(_, ref results)) = cache.iter().find(|&&(ref cached, _)| cached == select_def) {
+                return f(results);
+            }
+        }
 
-            return new_expr_parent;
-        } else {
-            return expr.clone();
+        let correlated = self.correlated.get();
+        self.correlated.set(false);
+
+        let results = select(db, select_def, Some(self), |_,_| {});
+        let value = f(&results);
+
+        if !self.correlated.get() {
+            db.subquery_cache.borrow_mut().push((select_def.clone(), results));
         }
+        self.correlated.set(correlated || self.correlated.get());
+
+        value
     }
 
-    pub fn eval_expr(&'a self, expr: &Expression) -> ExpressionResult {
-        match expr {
-            &Expression::LiteralValue(ref value) => ExpressionResult::Value(value.clone()),
-            &Expression::TableName(..) | &Expression::ColumnName(..) => self.eval_column_name(expr, None, None),
-            &Expression::BinaryOperator((b, ref expr1, ref expr2)) =>  {
-                if !self.order_pass.get() {
-                    debug!("order_of_op before: {:?}", expr);
-                    let expr = self.order_of_operations(expr);
-                    debug!("order_of_op after: {:?}", expr);
-                    self.order_pass.set(true);
-                    self.eval_expr(&expr)
-                } else {
-                    self.eval_binary_operator(b, &**expr1, &**expr2)
-                }
+    fn eval_outer_column_name(&'a self, expr: &Expression) -> ExpressionResult {
+        match self.outer {
+            Some(outer) => {
+                outer.correlated.set(true);
+                outer.eval_expr(expr)
             }
-            &Expression::UnaryOperator((u, ref exp)) => self.eval_unary_operator(u, &**exp),
-            _ => ExpressionResult::Null,
+            None => ExpressionResult::Null,
         }
     }
 
@@ -143,6 +242,15 @@ impl<'a, 'b> ExpressionEvaluator<'a, 'b> {
                 let right = result_to_literal(self.eval_expr(expr2));
                 ExpressionResult::Value(left.ge(&right))
             }
+            BinaryOperator::Concat => {
+                let left = result_to_literal(self.eval_expr(expr1));
+                let right = result_to_literal(self.eval_expr(expr2));
+
+                match (left, right) {
+                    (LiteralValue::Null, _) | (_, LiteralValue::Null) => ExpressionResult::Value(LiteralValue::Null),
+                    (left, right) => ExpressionResult::Value(LiteralValue::Text(left.to_text() + right.to_text().as_slice())),
+                }
+            }
             BinaryOperator::LShift => {
                 let left = result_to_literal(self.eval_expr(expr1));
                 let right = result_to_literal(self.eval_expr(expr2));
@@ -188,8 +296,8 @@ impl<'a, 'b> ExpressionEvaluator<'a, 'b> {
             BinaryOperator::Minus => {
                 debug!("{:?} - {:?}", expr1, expr2);
                 let left = result_to_literal(self.eval_expr(expr1));
-                let right = result_to_literal(self.eval_expr(&self.neg(expr2)));
-                ExpressionResult::Value(left + right)
+                let right = result_to_literal(self.eval_expr(expr2));
+                ExpressionResult::Value(left - right)
             }
             BinaryOperator::Mult => {
                 debug!("{:?} * {:?}", expr1, expr2);
@@ -209,6 +317,63 @@ impl<'a, 'b> ExpressionEvaluator<'a, 'b> {
                 let right = result_to_literal(self.eval_expr(expr2));
                 ExpressionResult::Value(left % right)
             }
+            BinaryOperator::Like | BinaryOperator::NotLike | BinaryOperator::Glob | BinaryOperator::NotGlob => {
+                let value = result_to_literal(self.eval_expr(expr1));
+                let (pattern, escape) = match expr2 {
+                    &Expression::BinaryOperator((BinaryOperator::Escape, ref pattern, ref escape)) => {
+                        let pattern = result_to_literal(self.eval_expr(&**pattern));
+                        (pattern, Some(result_to_literal(self.eval_expr(&**escape))))
+                    }
+                    _ => (result_to_literal(self.eval_expr(expr2)), None),
+                };
+                let result = match operator {
+                    BinaryOperator::Like | BinaryOperator::NotLike => {
+                        match eval_like(&value, &pattern, escape.as_ref()) {
+                            Ok(result) => result,
+                            Err(e) => {
+                                println!("error: {}", e);
+                                LiteralValue::Null
+                            }
+                        }
+                    }
+                    _ => eval_glob(&value, &pattern),
+                };
+
+                ExpressionResult::Value(match (operator, result) {
+                    (BinaryOperator::NotLike, LiteralValue::Boolean(b))
+                        | (BinaryOperator::NotGlob, LiteralValue::Boolean(b)) => LiteralValue::Boolean(!b),
+                    (_, result) => result,
+                })
+            }
+            BinaryOperator::Between | BinaryOperator::NotBetween => {
+                let value = result_to_literal(self.eval_expr(expr1));
+                let (low, high) = match expr2 {
+                    &Expression::BinaryOperator((BinaryOperator::And, ref low, ref high)) => {
+                        (result_to_literal(self.eval_expr(&**low)), result_to_literal(self.eval_expr(&**high)))
+                    }
+                    _ => return ExpressionResult::Null,
+                };
+
+                if value == LiteralValue::Null || low == LiteralValue::Null || high == LiteralValue::Null {
+                    return ExpressionResult::Value(LiteralValue::Null);
+                }
+
+                let between = value.ge(&low).to_bool() && value.le(&high).to_bool();
+                ExpressionResult::Value(LiteralValue::Boolean(between != (operator == BinaryOperator::NotBetween)))
+            }
+            BinaryOperator::In | BinaryOperator::NotIn => {
+                let value = result_to_literal(self.eval_expr(expr1));
+                let values: Vec<LiteralValue> = match expr2 {
+                    &Expression::List(ref exprs) => {
+                        exprs.iter().map(|expr| result_to_literal(self.eval_expr(expr))).collect()
+                    }
+                    _ => vec![result_to_literal(self.eval_expr(expr2))],
+                };
+
+                ExpressionResult::Value(eval_in(&value, values.iter(), operator == BinaryOperator::NotIn))
+            }
+            // Only meaningful as the pattern of a LIKE.
+            BinaryOperator::Escape => ExpressionResult::Null,
             BinaryOperator::Null => ExpressionResult::Null,
         }
     }
@@ -229,18 +394,57 @@ impl<'a, 'b> ExpressionEvaluator<'a, 'b> {
         }
     }
 
+    fn eval_function(&'a self, call: &FunctionCall) -> ExpressionResult {
+        match call.lower_name().as_slice() {
+            "last_insert_rowid" => {
+                match self.db {
+                    Some(db) => ExpressionResult::Value(LiteralValue::Integer(db.last_insert_rowid as isize)),
+                    None => ExpressionResult::Null,
+                }
+            }
+            "changes" => {
+                match self.db {
+                    Some(db) => ExpressionResult::Value(LiteralValue::Integer(db.changes as isize)),
+                    None => ExpressionResult::Null,
+                }
+            }
+            // Only the arguments up to the first non-NULL one are evaluated.
+            "coalesce" | "ifnull" => {
+                for arg in call.args.iter() {
+                    match result_to_literal(self.eval_expr(arg)) {
+                        LiteralValue::Null => continue,
+                        value => return ExpressionResult::Value(value),
+                    }
+                }
+                ExpressionResult::Value(LiteralValue::Null)
+            }
+            name => {
+                let args = call.args.iter().map(|arg| result_to_literal(self.eval_expr(arg))).collect();
+                match eval_scalar_function(name, args) {
+                    Ok(value) => ExpressionResult::Value(value),
+                    Err(e) => {
+                        self.raise_error(e);
+                        ExpressionResult::Null
+                    }
+                }
+            }
+        }
+    }
+
     fn eval_column_name(&'a self, expr: &Expression, table: Option<&Table>, offset: Option<usize>) -> ExpressionResult {
         match expr {
             &Expression::TableName((ref name, ref expr)) => {
                 let mut table_opt: Option<&Table> = None;
                 let mut offset = 0us;
 
-                for table in self.tables.clone().unwrap().into_iter() {
+                // Each occurrence of a table in the FROM clause is its own range
+                // variable, found by its alias and located by its position.
+                for table in self.tables.as_ref().unwrap().iter() {
                     if &table.name == name {
-                        table_opt = Some(table);
+                        table_opt = Some(table.table);
                         break;
                     }
-                    offset = offset + table.header.len();
+                    offset = offset + table.input_width();
                 }
                 if !table_opt.is_some() {
                     return ExpressionResult::Null;
@@ -257,35 +461,45 @@ impl<'a, 'b> ExpressionEvaluator<'a, 'b> {
         if self.get_column_def {
             if let Some(table) = table {
                 // We know which table to grab the def from...
-                if let Some(column_def) = table.get_column_def_by_name(name) {
-                    return ExpressionResult::ColumnDef(column_def.clone());
+                if let Some(column_def) = table.get_input_column_def(name) {
+                    return ExpressionResult::ColumnDef(column_def);
                 }
             } else {
                 // FIXME what if there are _other_ columns with the same name
                 // further down?
                 if let Some(ref tables) = self.tables {
                     for table in tables.iter() {
-                        if let Some(column_def) = table.get_column_def_by_name(name) {
-                            return ExpressionResult::ColumnDef(column_def.clone());
+                        if let Some(column_def) = table.get_input_column_def(name) {
+                            return ExpressionResult::ColumnDef(column_def);
                         }
                     }
                 }
             }
         } else {
-            if self.as_column_alias {
-                return ExpressionResult::Value(LiteralValue::Integer(
-                        // FIXME here I go with those blind unwraps again...
-                        self.head.iter().position(|ref cols| &cols.name == name).unwrap() as isize));
-            }
             if let Some(table) = table {
-                return ExpressionResult::Value(get_column(name, self.row, &table.header, offset));
+                if let Some(x) = table.get_input_column_index(name) {
+                    return ExpressionResult::Value(self.row[x + offset.unwrap_or(0)].clone());
+                }
             } else if let Some(ref tables) = self.tables {
+                // The first range variable with the column wins, except that a
+                // column merged by USING or NATURAL takes the first non-NULL value.
+                let mut value: Option<LiteralValue> = None;
                 let mut offset = 0us;
                 for table in tables.iter() {
-                    if let Some(_) = table.get_column_def_by_name(name) {
-                        return ExpressionResult::Value(get_column(name, self.row, &table.header, Some(offset)));
+                    if let Some(x) = table.get_input_column_index(name) {
+                        let take = match value {
+                            None => true,
+                            Some(LiteralValue::Null) => table.is_merged(name),
+                            Some(_) => false,
+                        };
+                        if take {
+                            value = Some(self.row[x + offset].clone());
+                        }
                     }
-                    offset += table.header.len();
+                    offset += table.input_width();
+                }
+                if let Some(value) = value {
+                    return ExpressionResult::Value(value);
                 }
             } else {
                 return ExpressionResult::Value(get_column(name, self.row, self.head, offset));
@@ -293,20 +507,6 @@ impl<'a, 'b> ExpressionEvaluator<'a, 'b> {
         }
         ExpressionResult::Null
     }
-
-    fn neg(&'a self, expr: &Expression) -> Expression {
-        match expr {
-            &Expression::LiteralValue(ref lit) => {
-                match lit {
-                    &LiteralValue::Integer(i) => Expression::LiteralValue(LiteralValue::Integer(-i)),
-                    _ => expr.clone()
-                }
-            }
-            &Expression::BinaryOperator((b, ref expr1, ref expr2)) => Expression::BinaryOperator((b.neg(), box self.neg(&**expr1), box self.neg(&**expr2))),
-            &Expression::UnaryOperator((u, ref expr)) => Expression::UnaryOperator((u.neg(), expr.clone())),
-            _ => expr.clone()
-        }
-    }
 }
 
 pub fn result_to_literal(result: ExpressionResult) -> LiteralValue {
@@ -322,3 +522,256 @@ pub fn expr_to_literal(expr: &Expression) -> LiteralValue {
         _ => LiteralValue::Null,
     }
 }
+
+// For COUNT(*) there is one (non-NULL) value per row.
+pub fn eval_aggregate(call: &FunctionCall, values: Vec<LiteralValue>,
+                      separator: LiteralValue) -> Result<LiteralValue, String> {
+    // https://www.sqlite.org/lang_aggfunc.html
+    let mut values: Vec<LiteralValue> = values.into_iter().filter(|v| *v != LiteralValue::Null).collect();
+
+    if call.distinct {
+        let mut distinct: Vec<LiteralValue> = Vec::new();
+        for value in values.into_iter() {
+            if !distinct.contains(&value) {
+                distinct.push(value);
+            }
+        }
+        values = distinct;
+    }
+
+    let total = values.iter().fold(0.0, |sum, v| sum + v.to_real());
+
+    Ok(match call.lower_name().as_slice() {
+        "count" => LiteralValue::Integer(values.len() as isize),
+        "sum" if values.len() == 0 => LiteralValue::Null,
+        "sum" if values.iter().all(|v| v.is_int()) => {
+            match values.iter().fold(Some(0), |sum, v| sum.and_then(|sum: isize| sum.checked_add(v.to_int()))) {
+                Some(sum) => LiteralValue::Integer(sum),
+                None => return Err("integer overflow".to_string()),
+            }
+        }
+        "sum" | "total" => LiteralValue::Real(total),
+        "avg" if values.len() == 0 => LiteralValue::Null,
+        "avg" => LiteralValue::Real(total / values.len() as f64),
+        "min" => values.into_iter().fold(LiteralValue::Null, |min, v| {
+            if min == LiteralValue::Null || v.cmp(&min) == Ordering::Less { v } else { min }
+        }),
+        "max" => values.into_iter().fold(LiteralValue::Null, |max, v| {
+            if max == LiteralValue::Null || v.cmp(&max) == Ordering::Greater { v } else { max }
+        }),
+        "group_concat" if values.len() == 0 => LiteralValue::Null,
+        "group_concat" => {
+            let separator = match separator {
+                LiteralValue::Null => ",".to_string(),
+                separator => format!("{}", separator),
+            };
+            let strs: Vec<String> = values.iter().map(|v| format!("{}", v)).collect();
+
+            LiteralValue::Text(strs.connect(separator.as_slice()))
+        }
+        _ => LiteralValue::Null,
+    })
+}
+
+pub fn eval_in<'c, I: Iterator<Item=&'c LiteralValue>>(value: &LiteralValue, values: I, negated: bool) -> LiteralValue {
+    let mut empty = true;
+    let mut has_null = false;
+
+    for other in values {
+        if *other == LiteralValue::Null {
+            has_null = true;
+        } else if other == value {
+            return LiteralValue::Boolean(!negated);
+        }
+        empty = false;
+    }
+
+    if !empty && (has_null || *value == LiteralValue::Null) {
+        LiteralValue::Null
+    } else {
+        LiteralValue::Boolean(negated)
+    }
+}
+
+// `prepare` can only check this ahead of time when the columns are listed.
+fn is_single_column(results: &Table) -> bool {
+    if results.header.len() > 1 {
+        println!("error: sub-select returns {} columns - expected 1", results.header.len());
+        return false;
+    }
+    true
+}
+
+enum PatternToken {
+    AnyRun,
+    AnyChar,
+    Char(char),
+    Class((Vec<(char, char)>, bool)),
+}
+
+fn eval_like(value: &LiteralValue, pattern: &LiteralValue,
+             escape: Option<&LiteralValue>) -> Result<LiteralValue, String> {
+    // https://www.sqlite.org/lang_expr.html#like
+    let escape = match escape {
+        Some(&LiteralValue::Null) => return Ok(LiteralValue::Null),
+        Some(escape) => {
+            let chars: Vec<char> = format!("{}", escape).chars().collect();
+            if chars.len() != 1 {
+                return Err("ESCAPE expression must be a single character".to_string());
+            }
+            Some(chars[0])
+        }
+        None => None,
+    };
+
+    Ok(match (value, pattern) {
+        (&LiteralValue::Null, _) | (_, &LiteralValue::Null) => LiteralValue::Null,
+        _ => {
+            let chars: Vec<char> = format!("{}", value).chars().collect();
+            let tokens = like_tokens(format!("{}", pattern).as_slice(), escape);
+
+            LiteralValue::Boolean(match_pattern(tokens.as_slice(), chars.as_slice(), true))
+        }
+    })
+}
+
+fn eval_glob(value: &LiteralValue, pattern: &LiteralValue) -> LiteralValue {
+    // https://www.sqlite.org/lang_expr.html#glob
+    match (value, pattern) {
+        (&LiteralValue::Null, _) | (_, &LiteralValue::Null) => LiteralValue::Null,
+        _ => {
+            let chars: Vec<char> = format!("{}", value).chars().collect();
+            let tokens = glob_tokens(format!("{}", pattern).as_slice());
+
+            LiteralValue::Boolean(match_pattern(tokens.as_slice(), chars.as_slice(), false))
+        }
+    }
+}
+
+fn like_tokens(pattern: &str, escape: Option<char>) -> Vec<PatternToken> {
+    let mut tokens: Vec<PatternToken> = Vec::new();
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        if Some(c) == escape {
+            if let Some(c) = chars.next() {
+                tokens.push(PatternToken::Char(c));
+            }
+            continue;
+        }
+
+        push_token(&mut tokens, match c {
+            '%' => PatternToken::AnyRun,
+            '_' => PatternToken::AnyChar,
+            c => PatternToken::Char(c),
+        });
+    }
+
+    tokens
+}
+
+fn glob_tokens(pattern: &str) -> Vec<PatternToken> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens: Vec<PatternToken> = Vec::new();
+    let mut i = 0us;
+
+    while i < chars.len() {
+        match chars[i] {
+            '*' => push_token(&mut tokens, PatternToken::AnyRun),
+            '?' => tokens.push(PatternToken::AnyChar),
+            '[' => {
+                let mut j = i + 1;
+                let negated = j < chars.len() && chars[j] == '^';
+                if negated {
+                    j += 1;
+                }
+
+                // A ']' right at the start of the class is one of its characters.
+                let start = j;
+                let mut ranges: Vec<(char, char)> = Vec::new();
+
+                while j < chars.len() && (chars[j] != ']' || j == start) {
+                    if j + 2 < chars.len() && chars[j + 1] == '-' && chars[j + 2] != ']' {
+                        ranges.push((chars[j], chars[j + 2]));
+                        j += 3;
+                    } else {
+                        ranges.push((chars[j], chars[j]));
+                        j += 1;
+                    }
+                }
+
+                tokens.push(PatternToken::Class((ranges, negated)));
+                i = j;
+            }
+            c => tokens.push(PatternToken::Char(c)),
+        }
+        i += 1;
+    }
+
+    tokens
+}
+
+fn push_token(tokens: &mut Vec<PatternToken>, token: PatternToken) {
+    match (tokens.last(), &token) {
+        (Some(&PatternToken::AnyRun), &PatternToken::AnyRun) => return,
+        _ => {}
+    }
+    tokens.push(token);
+}
+
+// On a mismatch only the last AnyRun is backtracked to, since whatever the
+// earlier ones matched the later one can match as well.
+fn match_pattern(tokens: &[PatternToken], chars: &[char], ignore_case: bool) -> bool {
+    let (mut t, mut c) = (0us, 0us);
+    // The token after the last `AnyRun`, and where its run of characters ends.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while c < chars.len() {
+        match tokens.get(t) {
+            Some(&PatternToken::AnyRun) => {
+                t += 1;
+                backtrack = Some((t, c));
+                continue;
+            }
+            Some(token) if token_matches(token, chars[c], ignore_case) => {
+                t += 1;
+                c += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        match backtrack {
+            Some((run_t, run_c)) => {
+                t = run_t;
+                c = run_c + 1;
+                backtrack = Some((run_t, c));
+            }
+            None => return false,
+        }
+    }
+
+    tokens[t..].iter().all(|token| match *token {
+        PatternToken::AnyRun => true,
+        _ => false,
+    })
+}
+
+fn token_matches(token: &PatternToken, c: char, ignore_case: bool) -> bool {
+    match *token {
+        PatternToken::AnyRun | PatternToken::AnyChar => true,
+        PatternToken::Char(other) if ignore_case => ascii_lowercase(other) == ascii_lowercase(c),
+        PatternToken::Char(other) => other == c,
+        PatternToken::Class((ref ranges, negated)) => {
+            ranges.iter().any(|&(low, high)| low <= c && c <= high) != negated
+        }
+    }
+}
+
+fn ascii_lowercase(c: char) -> char {
+    if c >= 'A' && c <= 'Z' {
+        ((c as u8) + (b'a' - b'A')) as char
+    } else {
+        c
+    }
+}
@@ -0,0 +1,309 @@
+use definitions::LiteralValue;
+
+use std::ascii::AsciiExt;
+use std::cmp::Ordering::{Less, Equal, Greater};
+use std::isize;
+use std::iter::repeat;
+use std::rand;
+
+pub fn eval_scalar_function(name: &str, args: Vec<LiteralValue>) -> Result<LiteralValue, String> {
+    // https://www.sqlite.org/lang_corefunc.html
+    Ok(match name {
+        "abs" => try!(abs(&args[0])),
+        "length" => match args[0] {
+            LiteralValue::Null => LiteralValue::Null,
+            ref value => LiteralValue::Integer(value.to_text().chars().count() as isize),
+        },
+        "lower" => map_text(&args[0], |s| s.to_ascii_lowercase()),
+        "upper" => map_text(&args[0], |s| s.to_ascii_uppercase()),
+        "substr" | "substring" => substr(&args[0], &args[1], args.get(2)),
+        "trim" | "ltrim" | "rtrim" => trim(name, &args[0], args.get(1)),
+        "replace" => replace(&args[0], &args[1], &args[2]),
+        "instr" => instr(&args[0], &args[1]),
+        "nullif" if args[0] == args[1] => LiteralValue::Null,
+        "nullif" => args[0].clone(),
+        "round" => round(&args[0], args.get(1)),
+        "min" | "max" => min_max(name, args),
+        "hex" => hex(&args[0]),
+        "quote" => quote(&args[0]),
+        "random" => LiteralValue::Integer(rand::random::<isize>()),
+        "printf" | "format" => printf(args.as_slice()),
+        _ => LiteralValue::Null,
+    })
+}
+
+fn map_text<F: Fn(&str) -> String>(value: &LiteralValue, f: F) -> LiteralValue {
+    match *value {
+        LiteralValue::Null => LiteralValue::Null,
+        ref value => LiteralValue::Text(f(value.to_text().as_slice())),
+    }
+}
+
+fn abs(value: &LiteralValue) -> Result<LiteralValue, String> {
+    Ok(match *value {
+        LiteralValue::Null => LiteralValue::Null,
+        LiteralValue::Integer(i) if i == isize::MIN => return Err("integer overflow".to_string()),
+        LiteralValue::Integer(i) => LiteralValue::Integer(i.abs()),
+        LiteralValue::Boolean(b) => LiteralValue::Integer(if b { 1 } else { 0 }),
+        ref value => LiteralValue::Real(value.to_real().abs()),
+    })
+}
+
+fn substr(value: &LiteralValue, start: &LiteralValue, length: Option<&LiteralValue>) -> LiteralValue {
+    if *value == LiteralValue::Null || *start == LiteralValue::Null || length == Some(&LiteralValue::Null) {
+        return LiteralValue::Null;
+    }
+
+    let chars: Vec<char> = value.to_text().chars().collect();
+    let len = chars.len() as isize;
+    let mut start = start.to_int();
+    let (mut length, backwards) = match length {
+        Some(length) if length.to_int() < 0 => (-length.to_int(), true),
+        Some(length) => (length.to_int(), false),
+        None => (isize::MAX, false),
+    };
+
+    if start < 0 {
+        start += len;
+        if start < 0 {
+            length = if length + start < 0 { 0 } else { length + start };
+            start = 0;
+        }
+    } else if start > 0 {
+        start -= 1;
+    } else if length > 0 {
+        length -= 1;
+    }
+
+    if backwards {
+        start -= length;
+        if start < 0 {
+            length += start;
+            start = 0;
+        }
+    }
+
+    let start = if start > len { len } else { start };
+    let end = if length > len - start { len } else { start + length };
+
+    LiteralValue::Text(chars[start as usize..end as usize].iter().map(|c| *c).collect())
+}
+
+fn trim(name: &str, value: &LiteralValue, chars: Option<&LiteralValue>) -> LiteralValue {
+    let chars: Vec<char> = match chars {
+        Some(&LiteralValue::Null) => return LiteralValue::Null,
+        Some(chars) => chars.to_text().chars().collect(),
+        None => vec![' '],
+    };
+
+    if *value == LiteralValue::Null {
+        return LiteralValue::Null;
+    }
+
+    let value = value.to_text();
+    let trimmed = match name {
+        "ltrim" => value.as_slice().trim_left_matches(chars.as_slice()),
+        "rtrim" => value.as_slice().trim_right_matches(chars.as_slice()),
+        _ => value.as_slice().trim_matches(chars.as_slice()),
+    };
+
+    LiteralValue::Text(trimmed.to_string())
+}
+
+fn replace(value: &LiteralValue, from: &LiteralValue, to: &LiteralValue) -> LiteralValue {
+    match (value, from, to) {
+        (&LiteralValue::Null, _, _) | (_, &LiteralValue::Null, _) | (_, _, &LiteralValue::Null) => {
+            LiteralValue::Null
+        }
+        _ if from.to_text().len() == 0 => LiteralValue::Text(value.to_text()),
+        _ => LiteralValue::Text(value.to_text().replace(from.to_text().as_slice(), to.to_text().as_slice())),
+    }
+}
+
+fn instr(haystack: &LiteralValue, needle: &LiteralValue) -> LiteralValue {
+    match (haystack, needle) {
+        (&LiteralValue::Null, _) | (_, &LiteralValue::Null) => LiteralValue::Null,
+        _ => {
+            let haystack = haystack.to_text();
+
+            match haystack.as_slice().find_str(needle.to_text().as_slice()) {
+                Some(x) => LiteralValue::Integer(haystack[..x].chars().count() as isize + 1),
+                None => LiteralValue::Integer(0),
+            }
+        }
+    }
+}
+
+fn round(value: &LiteralValue, digits: Option<&LiteralValue>) -> LiteralValue {
+    if *value == LiteralValue::Null || digits == Some(&LiteralValue::Null) {
+        return LiteralValue::Null;
+    }
+
+    let digits = digits.map_or(0, |digits| digits.to_int());
+    let factor = 10.0f64.powi(if digits < 0 { 0 } else { digits as i32 });
+
+    LiteralValue::Real((value.to_real() * factor).round() / factor)
+}
+
+fn min_max(name: &str, args: Vec<LiteralValue>) -> LiteralValue {
+    if args.contains(&LiteralValue::Null) {
+        return LiteralValue::Null;
+    }
+
+    args.into_iter().fold(LiteralValue::Null, |best, value| {
+        let better = match value.sort_cmp(&best) {
+            Less => name == "min",
+            Greater => name == "max",
+            Equal => false,
+        };
+
+        if best == LiteralValue::Null || better { value } else { best }
+    })
+}
+
+fn hex(value: &LiteralValue) -> LiteralValue {
+    match *value {
+        LiteralValue::Null => LiteralValue::Text(String::new()),
+        ref value => {
+            let digits: Vec<String> = value.to_text().as_bytes().iter().map(|b| format!("{:02X}", b)).collect();
+            LiteralValue::Text(digits.concat())
+        }
+    }
+}
+
+fn quote(value: &LiteralValue) -> LiteralValue {
+    LiteralValue::Text(match *value {
+        LiteralValue::Null => "NULL".to_string(),
+        LiteralValue::Text(ref t) => format!("'{}'", t.replace("'", "''")),
+        ref value => value.to_text(),
+    })
+}
+
+// Only the -, + and 0 flags and the d, i, f, x, X, c, s, q and Q conversions.
+fn printf(args: &[LiteralValue]) -> LiteralValue {
+    let format: Vec<char> = match args[0] {
+        LiteralValue::Null => return LiteralValue::Null,
+        ref format => format.to_text().chars().collect(),
+    };
+    let null = LiteralValue::Null;
+    let mut args = args[1..].iter();
+    let mut result = String::new();
+    let mut i = 0us;
+
+    while i < format.len() {
+        if format[i] != '%' {
+            result.push(format[i]);
+            i += 1;
+            continue;
+        }
+        i += 1;
+
+        let (mut left, mut zero, mut sign) = (false, false, false);
+        while i < format.len() {
+            match format[i] {
+                '-' => left = true,
+                '0' => zero = true,
+                '+' => sign = true,
+                _ => break,
+            }
+            i += 1;
+        }
+
+        let width = printf_number(format.as_slice(), &mut i);
+        let precision = if i < format.len() && format[i] == '.' {
+            i += 1;
+            Some(printf_number(format.as_slice(), &mut i))
+        } else {
+            None
+        };
+
+        if i == format.len() {
+            break;
+        }
+        let conversion = format[i];
+        i += 1;
+
+        let (s, numeric) = match conversion {
+            '%' => {
+                result.push('%');
+                continue;
+            }
+            'd' | 'i' => {
+                let n = args.next().unwrap_or(&null).to_int();
+                (format!("{}{}", if sign && n >= 0 { "+" } else { "" }, n), true)
+            }
+            'f' => {
+                let n = args.next().unwrap_or(&null).to_real();
+                (format!("{}{:.*}", if sign && n >= 0.0 { "+" } else { "" }, precision.unwrap_or(6), n), true)
+            }
+            'x' => (format!("{:x}", args.next().unwrap_or(&null).to_int()), true),
+            'X' => (format!("{:X}", args.next().unwrap_or(&null).to_int()), true),
+            'c' => {
+                let s = match *args.next().unwrap_or(&null) {
+                    LiteralValue::Null => String::new(),
+                    ref value => value.to_text().chars().take(1).collect(),
+                };
+                (s, false)
+            }
+            's' | 'q' | 'Q' => {
+                let s = match *args.next().unwrap_or(&null) {
+                    LiteralValue::Null if conversion == 'Q' => "NULL".to_string(),
+                    LiteralValue::Null => String::new(),
+                    ref value => {
+                        let s = value.to_text();
+                        let s = match precision {
+                            Some(precision) => s.chars().take(precision).collect(),
+                            None => s,
+                        };
+                        match conversion {
+                            'q' => s.replace("'", "''"),
+                            'Q' => format!("'{}'", s.replace("'", "''")),
+                            _ => s,
+                        }
+                    }
+                };
+                (s, false)
+            }
+            c => {
+                result.push('%');
+                result.push(c);
+                continue;
+            }
+        };
+
+        result.push_str(pad(s, width, left, zero && numeric).as_slice());
+    }
+
+    LiteralValue::Text(result)
+}
+
+fn printf_number(format: &[char], i: &mut usize) -> usize {
+    let mut n = 0us;
+
+    while *i < format.len() {
+        match format[*i].to_digit(10) {
+            Some(digit) => n = n * 10 + digit as usize,
+            None => break,
+        }
+        *i += 1;
+    }
+
+    n
+}
+
+fn pad(s: String, width: usize, left: bool, zero: bool) -> String {
+    let n = s.chars().count();
+    if n >= width {
+        return s;
+    }
+
+    let padding: String = repeat(if zero && !left { '0' } else { ' ' }).take(width - n).collect();
+
+    if left {
+        s + padding.as_slice()
+    } else if zero && (s.starts_with("-") || s.starts_with("+")) {
+        format!("{}{}{}", &s[..1], padding, &s[1..])
+    } else {
+        padding + s.as_slice()
+    }
+}
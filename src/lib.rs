@@ -13,4 +13,5 @@ pub mod definitions;
 pub mod table;
 pub mod exec;
 pub mod expressions;
+pub mod functions;
 pub mod rusql;
@@ -1,10 +1,21 @@
-use definitions::TableDef;
-use table::Table;
+use definitions::{TableDef, ColumnDef, ColumnType, LiteralValue, SelectDef};
+use table::{Table, PkType};
 
+use std::cell::RefCell;
 use std::collections::BTreeMap;
 
+// The AUTOINCREMENT high-water marks, one (name, seq) row per table.
+pub static SEQUENCE_TABLE: &'static str = "rusql_sequence";
+
 pub struct Rusql {
     pub map: BTreeMap<String, Table>,
+    pub last_insert_rowid: PkType,
+    // The number of rows the last INSERT, UPDATE or DELETE changed.
+    pub changes: usize,
+    // Results of the uncorrelated subqueries run by the current statement.
+    pub subquery_cache: RefCell<Vec<(SelectDef, Table)>>,
+    // The first error raised while running the current statement, which aborts it.
+    pub error: RefCell<Option<String>>,
 }
 
 
@@ -12,11 +23,32 @@ impl Rusql {
     pub fn new() -> Rusql {
         return Rusql {
             map: BTreeMap::new(),
+            last_insert_rowid: 0,
+            changes: 0,
+            subquery_cache: RefCell::new(Vec::new()),
+            error: RefCell::new(None),
         };
     }
 
+    pub fn raise_error(&self, e: String) {
+        let mut error = self.error.borrow_mut();
+        if error.is_none() {
+            *error = Some(e);
+        }
+    }
+
+    pub fn failed(&self) -> bool {
+        self.error.borrow().is_some()
+    }
+
     pub fn rename_table(&mut self, old_name: &String, new_name: String) {
-        let table = self.map.remove(old_name.as_slice()).unwrap();
+        let mut table = self.map.remove(old_name.as_slice()).unwrap();
+        table.name = new_name.clone();
+
+        if let Some(seq) = self.get_sequence(old_name) {
+            self.remove_sequence(old_name);
+            self.set_sequence(&new_name, seq);
+        }
         self.map.insert(new_name, table);
     }
 
@@ -35,10 +67,99 @@ impl Rusql {
             }
         }
         let table = Table::new(table_def);
+
+        if table.autoincrement {
+            self.create_sequence_table();
+        }
         self.map.insert(table.name.clone(), table);
     }
 
     pub fn drop_table(&mut self, name: &String) {
         self.map.remove(name.as_slice());
+        self.remove_sequence(name);
+    }
+
+    fn create_sequence_table(&mut self) {
+        if self.map.contains_key(SEQUENCE_TABLE) {
+            return;
+        }
+
+        let columns = vec![
+            ColumnDef {
+                name: "name".to_string(),
+                column_type: Some(ColumnType::Text),
+                column_constraints: Vec::new(),
+            },
+            ColumnDef {
+                name: "seq".to_string(),
+                column_type: Some(ColumnType::Integer),
+                column_constraints: Vec::new(),
+            },
+        ];
+
+        self.create_table(TableDef {
+            table_name: SEQUENCE_TABLE.to_string(),
+            columns: columns,
+            if_not_exists: false,
+        });
+    }
+
+    fn sequence_key(&self, name: &String) -> Option<PkType> {
+        let name = LiteralValue::Text(name.clone());
+
+        match self.map.get(SEQUENCE_TABLE) {
+            Some(table) => table.data.iter().find(|&(_, row)| row[0] == name).map(|(key, _)| *key),
+            None => None,
+        }
+    }
+
+    pub fn get_sequence(&self, name: &String) -> Option<PkType> {
+        let name = LiteralValue::Text(name.clone());
+
+        match self.map.get(SEQUENCE_TABLE) {
+            Some(table) => table.data.values().find(|row| row[0] == name).map(|row| row[1].to_uint()),
+            None => None,
+        }
+    }
+
+    pub fn set_sequence(&mut self, name: &String, seq: PkType) {
+        self.create_sequence_table();
+        let key = self.sequence_key(name);
+        let table = self.get_mut_table(&SEQUENCE_TABLE.to_string());
+        let row = vec![LiteralValue::Text(name.clone()), LiteralValue::Integer(seq as isize)];
+
+        match key {
+            Some(key) => { table.data.insert(key, row); }
+            None => { table.push_row(row); }
+        }
+    }
+
+    fn remove_sequence(&mut self, name: &String) {
+        if let Some(key) = self.sequence_key(name) {
+            self.get_mut_table(&SEQUENCE_TABLE.to_string()).data.remove(&key);
+        }
+    }
+
+    // Edits made to the sequence table through SQL are honored by the next insert.
+    pub fn load_sequence(&self, name: &String) {
+        let table = self.get_table(name);
+
+        if table.autoincrement {
+            table.max_pk.set(match self.get_sequence(name) {
+                Some(seq) => seq,
+                None => 0,
+            });
+        }
+    }
+
+    pub fn store_sequence(&mut self, name: &String) {
+        let (autoincrement, seq) = {
+            let table = self.get_table(name);
+            (table.autoincrement, table.max_pk.get())
+        };
+
+        if autoincrement {
+            self.set_sequence(name, seq);
+        }
     }
 }
@@ -1,10 +1,15 @@
-use definitions::{TableDef, LiteralValue, ColumnDef, ColumnConstraint};
+use definitions::{TableDef, LiteralValue, ColumnDef, ColumnConstraint, ColumnType};
+use definitions::{Expression, GeneratedStorage};
+use expressions::{ExpressionEvaluator, result_to_literal};
+use rusql::Rusql;
 
+use std::ascii::AsciiExt;
 use std::cell::Cell;
-use std::cmp::max;
-use std::collections::BTreeMap;
+use std::cmp::{max, Ordering};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 use std::iter::repeat;
+use std::ops::Deref;
 
 pub type TableRow = Vec<LiteralValue>;
 pub type TableHeader = Vec<ColumnDef>;
@@ -13,6 +18,46 @@ pub type PkType = usize;
 pub struct RowFormat<'a>(pub &'a TableRow);
 pub struct HeaderFormat<'a>(pub &'a TableHeader);
 
+// A real column of the same name shadows these.
+pub static ROWID_NAMES: [&'static str; 3] = ["rowid", "_rowid_", "oid"];
+
+pub fn is_rowid_name(name: &String) -> bool {
+    ROWID_NAMES.iter().any(|rowid| rowid.eq_ignore_ascii_case(name.as_slice()))
+}
+
+#[derive(Clone, PartialEq)]
+pub struct InputTable<'a> {
+    pub name: String,
+    pub table: &'a Table,
+    // Columns merged by USING or NATURAL into a range variable to the left.
+    pub merged: Vec<String>,
+}
+
+impl<'a> InputTable<'a> {
+    pub fn new(table: &'a Table, alias: Option<&String>) -> InputTable<'a> {
+        InputTable {
+            name: match alias {
+                Some(alias) => alias.clone(),
+                None => table.name.clone(),
+            },
+            table: table,
+            merged: Vec::new(),
+        }
+    }
+
+    pub fn is_merged(&self, name: &String) -> bool {
+        self.merged.iter().any(|column| column == name)
+    }
+}
+
+impl<'a> Deref for InputTable<'a> {
+    type Target = Table;
+
+    fn deref(&self) -> &Table {
+        self.table
+    }
+}
+
 #[derive(PartialEq)]
 pub struct Table {
     pub name: String,
@@ -20,6 +65,7 @@ pub struct Table {
     pub data: BTreeMap<PkType, TableRow>,
     pub pk: Option<PkType>,
     pub max_pk: Cell<PkType>,
+    pub autoincrement: bool,
 }
 
 impl Table {
@@ -30,6 +76,7 @@ impl Table {
             data: BTreeMap::new(),
             pk: None,
             max_pk: Cell::new(0),
+            autoincrement: false,
         };
         table.process_constraints();
 
@@ -43,8 +90,10 @@ impl Table {
             data: BTreeMap::new(),
             pk: None,
             max_pk: Cell::new(0),
+            autoincrement: false,
         }
     }
+
     pub fn get_column_def_by_name(&self, name: &String) -> Option<&ColumnDef> {
         self.header.iter().find(|&cols| &cols.name == name)
     }
@@ -53,6 +102,104 @@ impl Table {
         self.header.iter().position(|ref cols| &cols.name == name)
     }
 
+    // Includes the hidden rowid column unless an INTEGER PRIMARY KEY aliases it.
+    pub fn input_width(&self) -> usize {
+        if self.pk.is_some() { self.header.len() } else { self.header.len() + 1 }
+    }
+
+    pub fn input_header(&self) -> TableHeader {
+        let mut header = self.header.clone();
+
+        if self.pk.is_none() {
+            header.push(ColumnDef {
+                name: ROWID_NAMES[0].to_string(),
+                column_type: Some(ColumnType::Integer),
+                column_constraints: Vec::new(),
+            });
+        }
+        header
+    }
+
+    pub fn input_row(&self, db: &Rusql, key: PkType, row: &TableRow) -> TableRow {
+        let mut row = row.clone();
+
+        if let Err(e) = self.compute_generated(&mut row, GeneratedStorage::Virtual) {
+            db.raise_error(e);
+        }
+
+        if self.pk.is_none() {
+            row.push(LiteralValue::Integer(key as isize));
+        }
+        row
+    }
+
+    pub fn get_input_column_index(&self, name: &String) -> Option<usize> {
+        match self.get_column_index(name) {
+            Some(x) => Some(x),
+            None if is_rowid_name(name) => Some(self.pk.unwrap_or(self.header.len())),
+            None => None,
+        }
+    }
+
+    pub fn get_input_column_def(&self, name: &String) -> Option<ColumnDef> {
+        match self.get_input_column_index(name) {
+            Some(x) => {
+                let mut def = self.input_header()[x].clone();
+                def.name = name.clone();
+                Some(def)
+            }
+            None => None,
+        }
+    }
+
+    // The STORED columns are computed along with the VIRTUAL ones they may refer
+    // to, which are then left NULL until the row is read.
+    pub fn compute_generated(&self, row: &mut TableRow, storage: GeneratedStorage) -> Result<(), String> {
+        for i in try!(generated_order(&self.header)).into_iter() {
+            if let Some((expr, column_storage)) = self.header[i].generated() {
+                if column_storage == storage || storage == GeneratedStorage::Stored {
+                    let values = row.clone();
+                    let evaluator = ExpressionEvaluator::new(&values, &self.header);
+                    let value = result_to_literal(evaluator.eval_expr(expr));
+
+                    if let Some(e) = evaluator.take_error() {
+                        return Err(e);
+                    }
+                    row[i] = value;
+                }
+            }
+        }
+
+        if storage == GeneratedStorage::Stored {
+            for (i, column) in self.header.iter().enumerate() {
+                if let Some((_, GeneratedStorage::Virtual)) = column.generated() {
+                    row[i] = LiteralValue::Null;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check_writable(&self, name: &String) -> Result<(), String> {
+        match self.get_column_def_by_name(name) {
+            Some(def) if def.is_generated() => Err(format!("cannot modify generated column \"{}\"", name)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn next_rowid(&self) -> PkType {
+        let max_key = match self.data.keys().next_back() {
+            Some(key) => *key,
+            None => 0,
+        };
+
+        if self.autoincrement {
+            max(max_key, self.max_pk.get()) + 1
+        } else {
+            max_key + 1
+        }
+    }
+
     pub fn has_row(&self, pk: PkType) -> bool {
         self.data.contains_key(&pk)
     }
@@ -79,47 +226,141 @@ impl Table {
         }
     }
 
-    pub fn insert(&mut self, column_data: Vec<TableRow>,
-                  specified_columns: &Option<Vec<String>>) {
-        for column_data in column_data.into_iter() {
-            if let &Some(ref column_names) = specified_columns {
-                assert!(column_names.len() == column_data.len());
-                let mut row: TableRow = repeat(LiteralValue::Null).take(self.header.len()).collect();
-
-                for (name, data) in column_names.iter().zip(column_data.into_iter()) {
-                    row[self.get_column_index(name).unwrap()] = data;
-                }
+    // Without specified columns, the values go into those that aren't generated.
+    pub fn insert(&mut self, column_data: Vec<TableRow>, width: usize,
+                  specified_columns: &Option<Vec<String>>) -> Result<Vec<PkType>, String> {
+        let mut rowids: Vec<PkType> = Vec::new();
 
-                if let Some(i) = self.pk {
-                    if row[i] == LiteralValue::Null {
-                        row[i] = LiteralValue::Integer((self.max_pk.get() + 1) as isize);
+        // Generated columns can't be written, so without a column list the values
+        // map onto the remaining columns in order.
+        let column_names: Vec<String> = match specified_columns {
+            &Some(ref column_names) => {
+                for name in column_names.iter() {
+                    if self.get_column_index(name).is_none() {
+                        return Err(format!("table {} has no column named {}", self.name, name));
                     }
+                    try!(self.check_writable(name));
                 }
+                column_names.clone()
+            }
+            &None => self.header.iter().filter(|def| !def.is_generated())
+                                       .map(|def| def.name.clone())
+                                       .collect(),
+        };
 
-                self.push_row(row);
-            } else {
-                self.push_row(column_data);
+        if width != column_names.len() {
+            return Err(match specified_columns {
+                &Some(_) => format!("{} values for {} columns", width, column_names.len()),
+                &None => format!("table {} has {} columns but {} values were supplied",
+                                 self.name, column_names.len(), width),
+            });
+        }
+
+        let mut rows: Vec<TableRow> = Vec::new();
+
+        for column_data in column_data.into_iter() {
+            let mut row: TableRow = repeat(LiteralValue::Null).take(self.header.len()).collect();
+
+            for (name, data) in column_names.iter().zip(column_data.into_iter()) {
+                row[self.get_column_index(name).unwrap()] = data;
             }
+
+            try!(self.compute_generated(&mut row, GeneratedStorage::Stored));
+            rows.push(row);
+        }
+
+        try!(self.check_new_keys(&rows));
+
+        for row in rows.into_iter() {
+            rowids.push(self.push_row(row));
         }
+
+        Ok(rowids)
     }
 
-    pub fn push_row(&mut self, row: TableRow) {
-        if let Some(i) = self.pk {
-            let pk = row[i].clone().to_uint();
+    fn check_new_keys(&self, rows: &Vec<TableRow>) -> Result<(), String> {
+        let mut keys: BTreeSet<PkType> = BTreeSet::new();
+        let mut max_key = self.next_rowid() - 1;
 
-            self.max_pk.set(max(self.max_pk.get(), pk));
-            self.data.insert(pk, row);
-        } else {
-            self.max_pk.set(self.max_pk.get() + 1);
-            self.data.insert(self.max_pk.get(), row);
+        for row in rows.iter() {
+            // Like `push_row`, a NULL key is assigned the next rowid.
+            let key = match self.pk {
+                Some(i) if row[i] != LiteralValue::Null => row[i].to_uint(),
+                _ => max_key + 1,
+            };
+
+            if self.has_row(key) || !keys.insert(key) {
+                return Err(format!("UNIQUE constraint failed: {}.{}", self.name, self.pk_name()));
+            }
+            max_key = max(max_key, key);
         }
+
+        Ok(())
+    }
+
+    pub fn push_row(&mut self, mut row: TableRow) -> PkType {
+        let rowid = match self.pk {
+            Some(i) if row[i] != LiteralValue::Null => row[i].to_uint(),
+            Some(i) => {
+                let rowid = self.next_rowid();
+                row[i] = LiteralValue::Integer(rowid as isize);
+                rowid
+            }
+            None => self.next_rowid(),
+        };
+
+        self.max_pk.set(max(self.max_pk.get(), rowid));
+        self.data.insert(rowid, row);
+
+        rowid
     }
 
-    pub fn delete_where<F: Fn(&TableRow) -> bool>(&mut self, f: F) {
+    // Nothing is changed if two rows would end up under the same key.
+    pub fn update_rows(&mut self, rows: Vec<(PkType, PkType, TableRow)>) -> Result<(), String> {
+        let keys: BTreeSet<PkType> = rows.iter().map(|&(key, _, _)| key).collect();
+        let mut new_keys: BTreeSet<PkType> = BTreeSet::new();
+
+        for &(_, new_key, _) in rows.iter() {
+            // A key is free if its row is moving away, unless another row took it.
+            if !new_keys.insert(new_key) || (self.has_row(new_key) && !keys.contains(&new_key)) {
+                return Err(format!("UNIQUE constraint failed: {}.{}", self.name, self.pk_name()));
+            }
+        }
+
+        let mut new_rows: Vec<(PkType, PkType, TableRow)> = Vec::new();
+
+        for (key, new_key, mut row) in rows.into_iter() {
+            if let Some(i) = self.pk {
+                row[i] = LiteralValue::Integer(new_key as isize);
+            }
+            try!(self.compute_generated(&mut row, GeneratedStorage::Stored));
+            new_rows.push((key, new_key, row));
+        }
+
+        for &(key, _, _) in new_rows.iter() {
+            self.data.remove(&key);
+        }
+
+        for (_, new_key, row) in new_rows.into_iter() {
+            self.max_pk.set(max(self.max_pk.get(), new_key));
+            self.data.insert(new_key, row);
+        }
+
+        Ok(())
+    }
+
+    pub fn pk_name(&self) -> String {
+        match self.pk {
+            Some(i) => self.header[i].name.clone(),
+            None => ROWID_NAMES[0].to_string(),
+        }
+    }
+
+    pub fn delete_where<F: Fn(PkType, &TableRow) -> bool>(&mut self, f: F) {
         let mut keys: Vec<PkType> = Vec::new();
 
         for (key, row) in self.data.iter() {
-            if !f(row) {
+            if !f(*key, row) {
                 continue;
             }
             keys.push(key.clone());
@@ -138,10 +379,20 @@ impl Table {
         for (i, column) in self.header.iter().enumerate() {
             for constraint in column.column_constraints.iter() {
                 match constraint {
-                    &ColumnConstraint::PrimaryKey => self.pk = Some(i),
+                    // Only an INTEGER PRIMARY KEY becomes an alias for the rowid.
+                    &ColumnConstraint::PrimaryKey => {
+                        if column.column_type == Some(ColumnType::Integer) {
+                            self.pk = Some(i);
+                        }
+                    }
+                    &ColumnConstraint::AutoIncrement => self.autoincrement = true,
+                    &ColumnConstraint::Generated(..) => {}
                 }
             }
         }
+
+        // AUTOINCREMENT is only meaningful on an INTEGER PRIMARY KEY.
+        self.autoincrement = self.autoincrement && self.pk.is_some();
     }
 }
 
@@ -179,3 +430,74 @@ pub fn get_column(name: &String, row: &TableRow, head: &TableHeader, offset: Opt
     let x = if let Some(x) = offset { x } else { 0 };
     row[head.iter().position(|ref def| def.name == *name).unwrap() + x].clone()
 }
+
+// Orders the generated columns so that each comes after the ones it refers to.
+pub fn generated_order(header: &TableHeader) -> Result<Vec<usize>, String> {
+    let mut order: Vec<usize> = Vec::new();
+    let mut visiting: Vec<usize> = Vec::new();
+
+    for (i, _) in header.iter().enumerate() {
+        try!(visit_generated(header, i, &mut visiting, &mut order));
+    }
+    Ok(order)
+}
+
+fn visit_generated(header: &TableHeader, i: usize, visiting: &mut Vec<usize>,
+                   order: &mut Vec<usize>) -> Result<(), String> {
+    let expr = match header[i].generated() {
+        Some((expr, _)) if !order.contains(&i) => expr,
+        _ => return Ok(()),
+    };
+
+    if visiting.contains(&i) {
+        return Err(format!("generated column loop on \"{}\"", header[i].name));
+    }
+
+    visiting.push(i);
+    let mut refs: Vec<&Expression> = Vec::new();
+    expr.column_refs(&mut refs);
+    for column_ref in refs.into_iter() {
+        if let &Expression::ColumnName(ref name) = column_ref {
+            if let Some(j) = header.iter().position(|column_def| &column_def.name == name) {
+                try!(visit_generated(header, j, visiting, order));
+            }
+        }
+    }
+    visiting.pop();
+
+    order.push(i);
+    Ok(())
+}
+
+pub fn cmp_rows(a: &TableRow, b: &TableRow) -> Ordering {
+    for (x, y) in a.iter().zip(b.iter()) {
+        match x.sort_cmp(y) {
+            Ordering::Equal => continue,
+            ordering => return ordering,
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+// A row ordered by `cmp_rows`, so that sets of rows can be kept in a BTreeSet.
+pub struct OrderedRow(pub TableRow);
+
+impl PartialEq for OrderedRow {
+    fn eq(&self, other: &OrderedRow) -> bool {
+        cmp_rows(&self.0, &other.0) == Ordering::Equal
+    }
+}
+
+impl Eq for OrderedRow {}
+
+impl PartialOrd for OrderedRow {
+    fn partial_cmp(&self, other: &OrderedRow) -> Option<Ordering> {
+        Some(cmp_rows(&self.0, &other.0))
+    }
+}
+
+impl Ord for OrderedRow {
+    fn cmp(&self, other: &OrderedRow) -> Ordering {
+        cmp_rows(&self.0, &other.0)
+    }
+}
@@ -177,6 +177,90 @@ fn test_insert_with_select() {
     assert!(foo.data == foo2.data);
 }
 
+#[test]
+fn test_insert_with_select_into_columns() {
+    let mut db = init_db_and_insert_into_table();
+    let sql_str = "CREATE TABLE Bar(Id INTEGER PRIMARY KEY, Name TEXT, Tag TEXT); \
+                   INSERT INTO Bar(Tag, Name) SELECT \"t\", Name FROM Foo WHERE Id < 3; \
+                   INSERT INTO Bar(Tag) SELECT 1, 2;";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    let bar = db.get_table(&"Bar".to_string());
+    let rows: Vec<&Vec<LiteralValue>> = bar.data.values().collect();
+
+    assert_eq!(rows, vec![&vec![LiteralValue::Integer(1), LiteralValue::Text("Bar1".to_string()),
+                                LiteralValue::Text("t".to_string())],
+                          &vec![LiteralValue::Integer(2), LiteralValue::Text("Bar2".to_string()),
+                                LiteralValue::Text("t".to_string())]]);
+}
+
+#[test]
+fn test_insert_with_select_from_same_table() {
+    let mut db = init_db_and_insert_into_table();
+    let sql_str = "INSERT INTO Foo(Name) SELECT Name || \"!\" FROM Foo; \
+                   SELECT Id, Name FROM Foo WHERE Id > 4;";
+    let mut results: Vec<Vec<LiteralValue>> = Vec::new();
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row.clone());
+    });
+
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0], vec![LiteralValue::Integer(5), LiteralValue::Text("Bar1!".to_string())]);
+    assert_eq!(results[3], vec![LiteralValue::Integer(8), LiteralValue::Text("Bar4!".to_string())]);
+}
+
+#[test]
+fn test_insert_duplicate_keys() {
+    let mut db = init_db_and_insert_into_table();
+    let sql_str = "INSERT INTO Foo SELECT * FROM Foo; \
+                   INSERT INTO Foo VALUES(9, \"a\"), (9, \"b\"); \
+                   INSERT INTO Foo(Name) VALUES(\"c\"), (\"d\");";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    assert_eq!(select_column(&mut db, "SELECT Id FROM Foo;"), vec![1, 2, 3, 4, 5, 6]);
+}
+
+#[test]
+fn test_insert_values_referring_to_columns() {
+    let mut db = init_db_and_insert_into_table();
+    let sql_str = "INSERT INTO Foo VALUES(Id, \"a\"); \
+                   INSERT INTO Foo VALUES(9, upper(Foo.Name)); \
+                   INSERT INTO Foo VALUES(9, \"b\"), (10, Name);";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    assert_eq!(select_column(&mut db, "SELECT Id FROM Foo;"), vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn test_insert_expressions() {
+    let mut db = Rusql::new();
+    let mut results: Vec<Vec<LiteralValue>> = Vec::new();
+    let sql_str = "CREATE TABLE Foo(Num INTEGER, Name TEXT); \
+                   INSERT INTO Foo VALUES(-1, 'a' || 'b'), (2 * 3, upper(\"c\")), (abs(-7), substr(\"xyz\", 2)); \
+                   SELECT * FROM Foo;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row.clone());
+    });
+
+    assert_eq!(results, vec![vec![LiteralValue::Integer(-1), LiteralValue::Text("ab".to_string())],
+                             vec![LiteralValue::Integer(6), LiteralValue::Text("C".to_string())],
+                             vec![LiteralValue::Integer(7), LiteralValue::Text("yz".to_string())]]);
+}
+
+#[test]
+fn test_insert_wrong_number_of_values() {
+    let mut db = init_db_and_insert_into_table();
+    let sql_str = "INSERT INTO Foo VALUES(5, \"x\", 1); \
+                   INSERT INTO Foo(Name) VALUES(\"y\", 2); \
+                   INSERT INTO Foo VALUES(6, \"z\"), (7); \
+                   INSERT INTO Foo(Nope) VALUES(1);";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    assert_eq!(db.get_table(&"Foo".to_string()).data.len(), 4);
+}
+
 #[test]
 fn test_update() {
     let mut db = init_db_and_insert_into_table();
@@ -203,6 +287,142 @@ fn test_update_where() {
     assert!(results == expected);
 }
 
+#[test]
+fn test_update_with_expressions() {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE Counts(Id INTEGER, Count INTEGER, Prev INTEGER); \
+                   INSERT INTO Counts VALUES(1, 1, 0), (2, 5, 0); \
+                   UPDATE Counts SET Count = Count + 1, Prev = Count; \
+                   UPDATE Counts SET (Count, Prev) = (Count * 2, Count) WHERE Id = 2;";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    assert_eq!(select_rows(&mut db, "SELECT Id, Count, Prev, changes() FROM Counts;"),
+               vec![vec![1, 2, 1, 1], vec![2, 12, 6, 1]]);
+}
+
+#[test]
+fn test_update_mismatched_row_values() {
+    let mut db = init_db_and_insert_into_table();
+    let sql_str = "UPDATE Foo SET (Name) = (\"Qux\", 1); \
+                   SELECT Name FROM Foo WHERE Id=1;";
+    let mut results: Vec<LiteralValue> = Vec::new();
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row[0].clone());
+    });
+
+    assert_eq!(results, vec![LiteralValue::Text("Bar1".to_string())]);
+}
+
+#[test]
+fn test_update_primary_key() {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE Foo(Id INTEGER PRIMARY KEY, Name TEXT); \
+                   CREATE TABLE Bar(Name TEXT); \
+                   INSERT INTO Foo VALUES(1, 'a'), (2, 'b'); \
+                   INSERT INTO Bar VALUES('x'), ('y'); \
+                   UPDATE Foo SET Id = 10 WHERE Id = 1; \
+                   UPDATE Foo SET Id = 2 WHERE Id = 10; \
+                   INSERT INTO Foo(Name) VALUES('c'); \
+                   INSERT INTO Foo VALUES(1, 'd'); \
+                   UPDATE Bar SET rowid = 5 WHERE Name = 'x';";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    assert_eq!(select_rows(&mut db, "SELECT Id, rowid FROM Foo;"),
+               vec![vec![1, 1], vec![2, 2], vec![10, 10], vec![11, 11]]);
+    assert_eq!(select_rows(&mut db, "SELECT rowid FROM Bar;"), vec![vec![2], vec![5]]);
+}
+
+#[test]
+fn test_update_from() {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE Stock(Id INTEGER, Count INTEGER); \
+                   CREATE TABLE Sales(StockId INTEGER, Sold INTEGER); \
+                   INSERT INTO Stock VALUES(1, 10), (2, 20), (3, 30); \
+                   INSERT INTO Sales VALUES(1, 4), (3, 5), (3, 7); \
+                   UPDATE Stock SET Count = Count - Sold FROM Sales WHERE Stock.Id = Sales.StockId;";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    // Stock 3 matches two sales, and only the first is applied.
+    assert_eq!(select_rows(&mut db, "SELECT Id, Count, changes() FROM Stock;"),
+               vec![vec![1, 6, 2], vec![2, 20, 2], vec![3, 25, 2]]);
+}
+
+#[test]
+fn test_update_from_right_join() {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE Stock(Id INTEGER, Count INTEGER); \
+                   CREATE TABLE Sales(StockId INTEGER); \
+                   CREATE TABLE Returns(StockId INTEGER); \
+                   INSERT INTO Stock VALUES(1, 10), (2, 20); \
+                   INSERT INTO Sales VALUES(1); \
+                   INSERT INTO Returns VALUES(1), (5); \
+                   UPDATE Stock SET Count = count(*); \
+                   UPDATE Stock SET Count = Count + 1 FROM Sales RIGHT JOIN Returns ON Sales.StockId = Returns.StockId;";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    // The unmatched return gives a row without a Stock row, which is skipped.
+    assert_eq!(select_rows(&mut db, "SELECT Id, Count, changes() FROM Stock;"),
+               vec![vec![1, 11, 2], vec![2, 21, 2]]);
+}
+
+#[test]
+fn test_delete_with_subquery() {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE Foo(Id INTEGER); \
+                   CREATE TABLE Bar(FooId INTEGER); \
+                   INSERT INTO Foo VALUES(1), (2), (3), (4); \
+                   INSERT INTO Bar VALUES(2), (4); \
+                   DELETE FROM Foo WHERE EXISTS (SELECT 1 FROM Bar WHERE Bar.FooId = Foo.Id); \
+                   DELETE FROM Foo WHERE Id IN (SELECT FooId + 1 FROM Bar);";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    assert_eq!(select_rows(&mut db, "SELECT Id FROM Foo;"), vec![vec![1]]);
+}
+
+#[test]
+fn test_insert_returning() {
+    let mut db = init_db_and_insert_into_table();
+    let mut results: Vec<Vec<LiteralValue>> = Vec::new();
+    let sql_str = "INSERT INTO Foo(Name) VALUES(\"Bar5\"), (\"Bar6\") RETURNING Id, Name;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row.clone());
+    });
+
+    assert_eq!(results, vec![vec![LiteralValue::Integer(5), LiteralValue::Text("Bar5".to_string())],
+                             vec![LiteralValue::Integer(6), LiteralValue::Text("Bar6".to_string())]]);
+}
+
+#[test]
+fn test_update_returning() {
+    let mut db = init_db_and_insert_into_table();
+    let mut results: Vec<Vec<LiteralValue>> = Vec::new();
+    let sql_str = "UPDATE Foo SET Name = Name || \"!\" WHERE Id > 2 RETURNING *;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row.clone());
+    });
+
+    assert_eq!(results, vec![vec![LiteralValue::Integer(3), LiteralValue::Text("Bar3!".to_string())],
+                             vec![LiteralValue::Integer(4), LiteralValue::Text("Bar4!".to_string())]]);
+}
+
+#[test]
+fn test_delete_returning() {
+    let mut db = init_db_and_insert_into_table();
+    let mut names: Vec<String> = Vec::new();
+    let sql_str = "DELETE FROM Foo WHERE Id < 3 RETURNING Id * 10 AS Ten;";
+
+    let results_table = rusql_exec(&mut db, sql_str, |_, header| {
+        names.push(header[0].name.clone());
+    }).unwrap();
+
+    assert_eq!(names, vec!["Ten".to_string(), "Ten".to_string()]);
+    assert_eq!(results_table.data.values().map(|row| row[0].to_int()).collect::<Vec<isize>>(), vec![10, 20]);
+    assert_eq!(db.get_table(&"Foo".to_string()).data.len(), 2);
+}
+
 #[test]
 fn test_select_multiple_tables() {
     let mut db = Rusql::new();
@@ -358,6 +578,37 @@ fn test_select_order_by_desc() {
     assert_eq!(expected, results);
 }
 
+#[test]
+fn test_select_order_by_expressions() {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE Items(Id INTEGER, Price INTEGER, Qty INTEGER); \
+                   INSERT INTO Items VALUES(1, 5, 2), (2, 3, 5), (3, 10, 1), (4, 2, 5);";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    assert_eq!(select_rows(&mut db, "SELECT Id FROM Items ORDER BY Price * Qty DESC, Qty;"),
+               vec![vec![2], vec![3], vec![1], vec![4]]);
+    assert_eq!(select_rows(&mut db, "SELECT Id, Qty FROM Items ORDER BY 2 DESC, 1;"),
+               vec![vec![2, 5], vec![4, 5], vec![1, 2], vec![3, 1]]);
+    assert_eq!(select_rows(&mut db, "SELECT Qty FROM Items GROUP BY Qty ORDER BY count(*) DESC, Qty;"),
+               vec![vec![5], vec![1], vec![2]]);
+    assert_eq!(select_rows(&mut db, "SELECT Id FROM Items ORDER BY 2;"), Vec::<Vec<isize>>::new());
+}
+
+#[test]
+fn test_select_order_by_nulls() {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE a(Id INTEGER, b INTEGER); \
+                   INSERT INTO a VALUES(1, 2), (3, 1); \
+                   INSERT INTO a(Id) VALUES(2);";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    assert_eq!(select_rows(&mut db, "SELECT Id FROM a ORDER BY b;"), vec![vec![2], vec![3], vec![1]]);
+    assert_eq!(select_rows(&mut db, "SELECT Id FROM a ORDER BY b DESC;"), vec![vec![1], vec![3], vec![2]]);
+    assert_eq!(select_rows(&mut db, "SELECT Id FROM a ORDER BY b NULLS LAST;"), vec![vec![3], vec![1], vec![2]]);
+    assert_eq!(select_rows(&mut db, "SELECT Id FROM a ORDER BY b DESC NULLS FIRST;"),
+               vec![vec![2], vec![1], vec![3]]);
+}
+
 #[test]
 fn test_single_quote() {
     let mut db = init_db_with_table();
@@ -464,8 +715,8 @@ fn test_select_multiple_tables_with_natural_join() {
                    INSERT INTO b VALUES(1), (2);
                    SELECT * FROM a NATURAL JOIN b;";
 
-    let expected = vec![vec![1, 1],
-                        vec![2, 2]];
+    let expected = vec![vec![1],
+                        vec![2]];
     let mut results: Vec<Vec<isize>> = Vec::new();
 
     rusql_exec(&mut db, sql_str, |row, _| {
@@ -478,3 +729,979 @@ fn test_select_multiple_tables_with_natural_join() {
 
     assert_eq!(results, expected);
 }
+
+#[test]
+fn test_select_rowid() {
+    let mut db = Rusql::new();
+    let expected = vec![vec![1, 10], vec![2, 20]];
+    let mut results: Vec<Vec<isize>> = Vec::new();
+
+    let sql_str = "CREATE TABLE a(Num INTEGER); \
+                   INSERT INTO a VALUES(10), (20), (30); \
+                   SELECT rowid, Num FROM a WHERE _rowid_ < 3;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row.iter().map(|column| column.to_int()).collect());
+    });
+
+    assert_eq!(results, expected);
+}
+
+#[test]
+fn test_integer_primary_key_aliases_rowid() {
+    let mut db = init_db_and_insert_into_table();
+    let mut results: Vec<LiteralValue> = Vec::new();
+    let expected = vec![LiteralValue::Text("Qux".to_string())];
+
+    let sql_str = "UPDATE Foo SET Name=\"Qux\" WHERE oid=2; \
+                   SELECT Name FROM Foo WHERE rowid=2;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row[0].clone());
+    });
+
+    assert_eq!(results, expected);
+}
+
+#[test]
+fn test_rowid_reused_without_autoincrement() {
+    let mut db = init_db_and_insert_into_table();
+    let mut results: Vec<isize> = Vec::new();
+
+    let sql_str = "DELETE FROM Foo WHERE Id=4; \
+                   INSERT INTO Foo(Name) VALUES(\"Bar5\"); \
+                   SELECT Id FROM Foo;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row[0].to_int());
+    });
+
+    assert_eq!(results, vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn test_autoincrement_never_reuses_rowid() {
+    let mut db = Rusql::new();
+    let mut results: Vec<isize> = Vec::new();
+
+    let sql_str = "CREATE TABLE Foo(Id INTEGER PRIMARY KEY AUTOINCREMENT, Name TEXT); \
+                   INSERT INTO Foo(Name) VALUES(\"Bar1\"), (\"Bar2\"), (\"Bar3\"); \
+                   DELETE FROM Foo WHERE Id=3; \
+                   INSERT INTO Foo(Name) VALUES(\"Bar4\"); \
+                   SELECT Id FROM Foo;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row[0].to_int());
+    });
+
+    assert_eq!(results, vec![1, 2, 4]);
+    assert_eq!(db.get_sequence(&"Foo".to_string()), Some(4));
+}
+
+#[test]
+fn test_sequence_table_may_not_be_dropped_or_renamed() {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE Foo(Id INTEGER PRIMARY KEY AUTOINCREMENT, Name TEXT); \
+                   INSERT INTO Foo(Name) VALUES(\"Bar1\"), (\"Bar2\"); \
+                   DROP TABLE rusql_sequence; \
+                   ALTER TABLE rusql_sequence RENAME TO Seq; \
+                   DELETE FROM Foo; \
+                   INSERT INTO Foo(Name) VALUES(\"Bar3\");";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    assert_eq!(select_column(&mut db, "SELECT Id FROM Foo;"), vec![3]);
+    assert_eq!(db.get_sequence(&"Foo".to_string()), Some(3));
+}
+
+#[test]
+fn test_last_insert_rowid() {
+    let mut db = init_db_and_insert_into_table();
+
+    let results = rusql_exec(&mut db, "INSERT INTO Foo(Name) VALUES(\"Bar5\"); \
+                                       SELECT last_insert_rowid();", |_,_| {}).unwrap();
+
+    assert_eq!(results.data.get(&1).unwrap()[0], LiteralValue::Integer(5));
+}
+
+#[test]
+fn test_generated_columns() {
+    let mut db = Rusql::new();
+    let expected = vec![vec![2, 3, 6, 10],
+                        vec![4, 5, 20, 18]];
+    let mut results: Vec<Vec<isize>> = Vec::new();
+
+    let sql_str = "CREATE TABLE Box(W INTEGER, H INTEGER, \
+                                   Area INTEGER AS (W * H) STORED, \
+                                   Perimeter INTEGER GENERATED ALWAYS AS (W + W + H + H)); \
+                   INSERT INTO Box VALUES(2, 3), (1, 5); \
+                   UPDATE Box SET W=4 WHERE H=5; \
+                   SELECT * FROM Box;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row.iter().map(|column| column.to_int()).collect());
+    });
+
+    assert_eq!(results, expected);
+}
+
+#[test]
+fn test_generated_columns_are_read_only() {
+    let mut db = Rusql::new();
+
+    let sql_str = "CREATE TABLE Box(W INTEGER, H INTEGER, Area INTEGER AS (W * H) STORED); \
+                   INSERT INTO Box(W, H, Area) VALUES(2, 3, 7); \
+                   INSERT INTO Box VALUES(2, 3); \
+                   UPDATE Box SET Area=7;";
+
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    let table = db.get_table(&"Box".to_string());
+    assert_eq!(table.data.len(), 1);
+    assert_eq!(table.data.get(&1).unwrap()[2], LiteralValue::Integer(6));
+}
+
+#[test]
+fn test_stored_column_referring_to_virtual_column() {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE Box(W INTEGER, Double INTEGER AS (W * 2), Next INTEGER AS (Double + 1) STORED); \
+                   INSERT INTO Box VALUES(3);";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    assert_eq!(select_rows(&mut db, "SELECT * FROM Box;"), vec![vec![3, 6, 7]]);
+}
+
+#[test]
+fn test_generated_column_referring_to_later_column() {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE Box(S INTEGER AS (V + 1) STORED, V INTEGER AS (W * 2), W INTEGER, \
+                   T INTEGER AS (V + S)); \
+                   INSERT INTO Box(W) VALUES(3);";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    assert_eq!(select_rows(&mut db, "SELECT * FROM Box;"), vec![vec![7, 6, 3, 13]]);
+}
+
+#[test]
+fn test_generated_column_overflow() {
+    let mut db = Rusql::new();
+    let sql_str = format!("CREATE TABLE Num(N INTEGER, S INTEGER AS (abs(N)) STORED); \
+                           INSERT INTO Num VALUES(1), (0 - {0} - 1); \
+                           CREATE TABLE Big(N INTEGER); \
+                           INSERT INTO Big VALUES(1), (0 - {0} - 1); \
+                           ALTER TABLE Big ADD COLUMN V INTEGER AS (abs(N));", std::isize::MAX);
+    rusql_exec(&mut db, sql_str.as_slice(), |_,_| {});
+
+    assert_eq!(select_rows(&mut db, "SELECT COUNT(*) FROM Num;"), vec![vec![0]]);
+    assert!(rusql_exec(&mut db, "SELECT * FROM Big;", |_,_| {}).is_none());
+    assert_eq!(db.get_table(&"Big".to_string()).data.len(), 2);
+}
+
+#[test]
+fn test_generated_column_loop() {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE Box(A INTEGER AS (B), B INTEGER AS (A)); \
+                   CREATE TABLE Item(W INTEGER, A INTEGER AS (A + W)); \
+                   CREATE TABLE Line(W INTEGER, A INTEGER AS (W + 1)); \
+                   ALTER TABLE Line ADD COLUMN B INTEGER AS (B * 2);";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    assert!(!db.map.contains_key("Box"));
+    assert!(!db.map.contains_key("Item"));
+    assert_eq!(db.get_table(&"Line".to_string()).header.len(), 2);
+}
+
+#[test]
+fn test_generated_columns_referring_to_missing_columns() {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE Box(W INTEGER, Area INTEGER AS (W * H)); \
+                   CREATE TABLE Item(W INTEGER, Id INTEGER AS (rowid)); \
+                   CREATE TABLE Line(W INTEGER); \
+                   ALTER TABLE Line ADD COLUMN Area INTEGER AS (W * H);";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    assert!(!db.map.contains_key("Box"));
+    assert!(!db.map.contains_key("Item"));
+    assert_eq!(db.get_table(&"Line".to_string()).header.len(), 1);
+}
+
+#[test]
+fn test_aggregate_functions() {
+    let mut db = init_db_and_insert_into_table();
+    let expected = vec![LiteralValue::Integer(4),
+                        LiteralValue::Integer(10),
+                        LiteralValue::Real(2.5),
+                        LiteralValue::Integer(1),
+                        LiteralValue::Integer(4),
+                        LiteralValue::Real(10.0)];
+
+    let results = rusql_exec(&mut db, "SELECT COUNT(*), SUM(Id), AVG(Id), MIN(Id), MAX(Id), TOTAL(Id) FROM Foo;",
+                             |_,_| {}).unwrap();
+
+    assert_eq!(results.data.len(), 1);
+    assert_eq!(results.data.get(&1).unwrap(), &expected);
+}
+
+#[test]
+fn test_aggregate_functions_null_handling() {
+    let mut db = init_db_with_table();
+    let expected = vec![LiteralValue::Integer(3),
+                        LiteralValue::Integer(1),
+                        LiteralValue::Integer(2),
+                        LiteralValue::Text("Bar-Bar".to_string())];
+
+    let sql_str = "INSERT INTO Foo(Id) VALUES(1), (2); \
+                   INSERT INTO Foo(Name) VALUES(\"Bar\"), (\"Bar\"); \
+                   SELECT COUNT(*) - 1, COUNT(DISTINCT Name), COUNT(Name), GROUP_CONCAT(Name, \"-\") FROM Foo;";
+
+    let results = rusql_exec(&mut db, sql_str, |_,_| {}).unwrap();
+
+    assert_eq!(results.data.get(&1).unwrap(), &expected);
+}
+
+#[test]
+fn test_aggregate_functions_empty_table() {
+    let mut db = init_db_with_table();
+    let expected = vec![LiteralValue::Integer(0),
+                        LiteralValue::Null,
+                        LiteralValue::Null,
+                        LiteralValue::Real(0.0)];
+
+    let results = rusql_exec(&mut db, "SELECT COUNT(Id), SUM(Id), MAX(Name), TOTAL(Id) FROM Foo;", |_,_| {}).unwrap();
+
+    assert_eq!(results.data.get(&1).unwrap(), &expected);
+}
+
+#[test]
+fn test_aggregate_sum_overflow() {
+    let mut db = Rusql::new();
+    let sql_str = format!("CREATE TABLE Big(N INTEGER); \
+                           INSERT INTO Big VALUES({}), (1); \
+                           INSERT INTO Big SELECT SUM(N) FROM Big;", std::isize::MAX);
+    rusql_exec(&mut db, sql_str.as_slice(), |_,_| {});
+
+    assert!(rusql_exec(&mut db, "SELECT SUM(N), TOTAL(N) FROM Big;", |_,_| {}).is_none());
+    assert_eq!(select_rows(&mut db, "SELECT SUM(N) FROM Big;"), Vec::<Vec<isize>>::new());
+    assert_eq!(select_rows(&mut db, "SELECT COUNT(*), SUM(N) FROM Big WHERE N = 1;"), vec![vec![1, 1]]);
+}
+
+#[test]
+fn test_aggregate_misuse() {
+    let mut db = init_db_and_insert_into_table();
+    let sql_str = "SELECT Id FROM Foo WHERE Id > AVG(Id); \
+                   SELECT COUNT(*) FROM Foo GROUP BY MAX(Id); \
+                   SELECT Id FROM Foo LIMIT COUNT(*); \
+                   SELECT * FROM Foo JOIN Foo AS F ON F.Id = MIN(Foo.Id); \
+                   INSERT INTO Foo VALUES(9, MAX(1)); \
+                   DELETE FROM Foo WHERE Id = MAX(Id);";
+
+    assert_eq!(select_rows(&mut db, sql_str), Vec::<Vec<isize>>::new());
+    assert_eq!(select_column(&mut db, "SELECT Id FROM Foo;"), vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn test_group_by() {
+    let mut db = Rusql::new();
+    let expected = vec![vec![LiteralValue::Null, LiteralValue::Integer(1), LiteralValue::Integer(5)],
+                        vec![LiteralValue::Integer(1), LiteralValue::Integer(2), LiteralValue::Integer(30)],
+                        vec![LiteralValue::Integer(2), LiteralValue::Integer(1), LiteralValue::Integer(7)]];
+    let mut results: Vec<Vec<LiteralValue>> = Vec::new();
+
+    let sql_str = "CREATE TABLE Orders(CustomerId INTEGER, Amount INTEGER); \
+                   INSERT INTO Orders VALUES(2, 7), (1, 10), (1, 20); \
+                   INSERT INTO Orders(Amount) VALUES(5); \
+                   SELECT CustomerId, COUNT(*), SUM(Amount) FROM Orders GROUP BY CustomerId;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row.clone());
+    });
+
+    assert_eq!(results, expected);
+}
+
+#[test]
+fn test_group_by_having() {
+    let mut db = Rusql::new();
+    let expected = vec![vec![1, 30]];
+    let mut results: Vec<Vec<isize>> = Vec::new();
+
+    let sql_str = "CREATE TABLE Orders(CustomerId INTEGER, Amount INTEGER); \
+                   INSERT INTO Orders VALUES(2, 7), (1, 10), (1, 20), (3, 1); \
+                   SELECT CustomerId, SUM(Amount) FROM Orders GROUP BY CustomerId HAVING COUNT(*) > 1;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row.iter().map(|column| column.to_int()).collect());
+    });
+
+    assert_eq!(results, expected);
+}
+
+#[test]
+fn test_select_distinct() {
+    let mut db = init_db_with_table();
+    let expected = vec![LiteralValue::Text("Bar".to_string()),
+                        LiteralValue::Null,
+                        LiteralValue::Text("Qux".to_string())];
+    let mut results: Vec<LiteralValue> = Vec::new();
+
+    let sql_str = "INSERT INTO Foo(Name) VALUES(\"Bar\"), (\"Bar\"); \
+                   INSERT INTO Foo(Id) VALUES(3), (4); \
+                   INSERT INTO Foo(Name) VALUES(\"Qux\"), (\"Bar\"); \
+                   SELECT DISTINCT Name FROM Foo;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row[0].clone());
+    });
+
+    assert_eq!(results, expected);
+}
+
+#[test]
+fn test_select_all() {
+    let mut db = init_db_with_table();
+    let mut results: Vec<LiteralValue> = Vec::new();
+
+    let sql_str = "INSERT INTO Foo(Name) VALUES(\"Bar\"), (\"Bar\"); \
+                   SELECT ALL Name FROM Foo;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row[0].clone());
+    });
+
+    assert_eq!(results.len(), 2);
+}
+
+#[test]
+fn test_select_limit_offset() {
+    let mut db = init_db_and_insert_into_table();
+    let mut results: Vec<isize> = Vec::new();
+
+    rusql_exec(&mut db, "SELECT Id FROM Foo LIMIT 2;", |row, _| {
+        results.push(row[0].to_int());
+    });
+    rusql_exec(&mut db, "SELECT Id FROM Foo LIMIT 1 + 1 OFFSET 1;", |row, _| {
+        results.push(row[0].to_int());
+    });
+    rusql_exec(&mut db, "SELECT Id FROM Foo ORDER BY Id DESC LIMIT 1, 2;", |row, _| {
+        results.push(row[0].to_int());
+    });
+
+    assert_eq!(results, vec![1, 2, 2, 3, 3, 2]);
+}
+
+#[test]
+fn test_select_limit_with_joins() {
+    let mut db = init_db_and_insert_into_table();
+    let sql_str = "CREATE TABLE Bar(Num INTEGER); \
+                   INSERT INTO Bar VALUES(2), (4), (6);";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    assert_eq!(select_rows(&mut db, "SELECT Id, Num FROM Foo, Bar WHERE Num = Id * 2 LIMIT 2;"),
+               vec![vec![1, 2], vec![2, 4]]);
+    assert_eq!(select_rows(&mut db, "SELECT Id, Num FROM Foo LEFT JOIN Bar ON Num = Id LIMIT 3 OFFSET 1;"),
+               vec![vec![2, 2], vec![3, 0], vec![4, 4]]);
+    assert_eq!(select_rows(&mut db, "SELECT Id, Num FROM Bar RIGHT JOIN Foo ON Num = Id LIMIT 2 OFFSET 2;"),
+               vec![vec![1, 0], vec![3, 0]]);
+    assert_eq!(select_rows(&mut db, "SELECT COUNT(*) FROM Foo, Bar WHERE Num > Id LIMIT 1;"), vec![vec![8]]);
+}
+
+#[test]
+fn test_select_column_aliases() {
+    let mut db = init_db_and_insert_into_table();
+
+    let results = rusql_exec(&mut db, "SELECT Id AS Num, Id + 1, Name Nick, COUNT(*) FROM Foo;", |_,_| {}).unwrap();
+    let names: Vec<String> = results.header.iter().map(|def| def.name.clone()).collect();
+
+    assert_eq!(names, vec!["Num".to_string(), "Id + 1".to_string(), "Nick".to_string(), "COUNT(*)".to_string()]);
+    assert_eq!(results.data.get(&1).unwrap().len(), 4);
+}
+
+#[test]
+fn test_select_column_names_keep_parentheses() {
+    let mut db = init_db_and_insert_into_table();
+
+    let results = rusql_exec(&mut db, "SELECT (Id + 1) * 2, Id - (1 - 2), Id * 2 + 1, -(Id + 1), \
+                                       Id BETWEEN 1 AND 2 FROM Foo;", |_,_| {}).unwrap();
+    let names: Vec<String> = results.header.iter().map(|def| def.name.clone()).collect();
+
+    assert_eq!(names, vec!["(Id + 1) * 2".to_string(), "Id - (1 - 2)".to_string(), "Id * 2 + 1".to_string(),
+                           "-(Id + 1)".to_string(), "Id BETWEEN 1 AND 2".to_string()]);
+}
+
+#[test]
+fn test_select_table_aliases() {
+    let mut db = init_db_and_insert_into_table();
+    let mut results: Vec<isize> = Vec::new();
+
+    let sql_str = "SELECT f.Id AS Num FROM Foo AS f WHERE f.Id > 2 ORDER BY Num DESC;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row[0].to_int());
+    });
+    rusql_exec(&mut db, "SELECT f.Id FROM Foo f WHERE f.Name = \"Bar1\";", |row, _| {
+        results.push(row[0].to_int());
+    });
+
+    assert_eq!(results, vec![4, 3, 1]);
+}
+
+#[test]
+fn test_self_join_with_aliases() {
+    let mut db = Rusql::new();
+    let expected = vec![vec![LiteralValue::Text("Bob".to_string()), LiteralValue::Text("Alice".to_string())],
+                        vec![LiteralValue::Text("Carol".to_string()), LiteralValue::Text("Alice".to_string())],
+                        vec![LiteralValue::Text("Dave".to_string()), LiteralValue::Text("Carol".to_string())]];
+    let mut results: Vec<Vec<LiteralValue>> = Vec::new();
+
+    let sql_str = "CREATE TABLE Emp(Id INTEGER PRIMARY KEY, Name TEXT, ManagerId INTEGER); \
+                   INSERT INTO Emp(Id, Name) VALUES(1, \"Alice\"); \
+                   INSERT INTO Emp VALUES(2, \"Bob\", 1), (3, \"Carol\", 1), (4, \"Dave\", 3); \
+                   SELECT e.Name, m.Name FROM Emp e JOIN Emp m ON e.ManagerId = m.Id;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row.clone());
+    });
+
+    assert_eq!(results, expected);
+}
+
+#[test]
+fn test_self_natural_join_with_aliases() {
+    let mut db = Rusql::new();
+    let mut count = 0;
+
+    let sql_str = "CREATE TABLE a(Num INTEGER); \
+                   INSERT INTO a VALUES(1), (2), (3); \
+                   SELECT * FROM a x NATURAL JOIN a y;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        assert_eq!(row.len(), 1);
+        count += 1;
+    });
+
+    assert_eq!(count, 3);
+}
+
+fn init_db_for_outer_joins() -> Rusql {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE a(NumA INTEGER); \
+                   CREATE TABLE b(NumB INTEGER); \
+                   INSERT INTO a VALUES(1), (2), (3); \
+                   INSERT INTO b VALUES(2), (3), (4);";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    db
+}
+
+#[test]
+fn test_select_left_join() {
+    let mut db = init_db_for_outer_joins();
+    let expected = vec![vec![LiteralValue::Integer(1), LiteralValue::Null],
+                        vec![LiteralValue::Integer(2), LiteralValue::Integer(2)],
+                        vec![LiteralValue::Integer(3), LiteralValue::Integer(3)]];
+    let mut results: Vec<Vec<LiteralValue>> = Vec::new();
+
+    rusql_exec(&mut db, "SELECT NumA, NumB FROM a LEFT OUTER JOIN b ON NumA = NumB;", |row, _| {
+        results.push(row.clone());
+    });
+    assert_eq!(results, expected);
+
+    results.clear();
+    rusql_exec(&mut db, "SELECT * FROM a LEFT JOIN b ON NumA = NumB;", |row, _| {
+        results.push(row.clone());
+    });
+    assert_eq!(results, expected);
+}
+
+#[test]
+fn test_select_left_join_on_versus_where() {
+    let mut db = init_db_for_outer_joins();
+    let mut results: Vec<Vec<LiteralValue>> = Vec::new();
+
+    // A restriction in ON only decides which rows match; unmatched rows are kept.
+    rusql_exec(&mut db, "SELECT NumA, NumB FROM a LEFT JOIN b ON NumA = NumB AND NumB > 2;", |row, _| {
+        results.push(row.clone());
+    });
+    assert_eq!(results, vec![vec![LiteralValue::Integer(1), LiteralValue::Null],
+                             vec![LiteralValue::Integer(2), LiteralValue::Null],
+                             vec![LiteralValue::Integer(3), LiteralValue::Integer(3)]]);
+
+    // The same restriction in WHERE removes rows after the join.
+    results.clear();
+    rusql_exec(&mut db, "SELECT NumA, NumB FROM a LEFT JOIN b ON NumA = NumB WHERE NumB > 2;", |row, _| {
+        results.push(row.clone());
+    });
+    assert_eq!(results, vec![vec![LiteralValue::Integer(3), LiteralValue::Integer(3)]]);
+}
+
+#[test]
+fn test_select_right_and_full_join() {
+    let mut db = init_db_for_outer_joins();
+    let mut results: Vec<Vec<LiteralValue>> = Vec::new();
+
+    rusql_exec(&mut db, "SELECT NumA, NumB FROM a RIGHT JOIN b ON NumA = NumB;", |row, _| {
+        results.push(row.clone());
+    });
+    assert_eq!(results, vec![vec![LiteralValue::Integer(2), LiteralValue::Integer(2)],
+                             vec![LiteralValue::Integer(3), LiteralValue::Integer(3)],
+                             vec![LiteralValue::Null, LiteralValue::Integer(4)]]);
+
+    results.clear();
+    rusql_exec(&mut db, "SELECT NumA, NumB FROM a FULL OUTER JOIN b ON NumA = NumB;", |row, _| {
+        results.push(row.clone());
+    });
+    assert_eq!(results, vec![vec![LiteralValue::Integer(1), LiteralValue::Null],
+                             vec![LiteralValue::Integer(2), LiteralValue::Integer(2)],
+                             vec![LiteralValue::Integer(3), LiteralValue::Integer(3)],
+                             vec![LiteralValue::Null, LiteralValue::Integer(4)]]);
+}
+
+#[test]
+fn test_select_cross_join() {
+    let mut db = init_db_for_outer_joins();
+    let mut count = 0;
+
+    rusql_exec(&mut db, "SELECT * FROM a CROSS JOIN b;", |_, _| {
+        count += 1;
+    });
+    assert_eq!(count, 9);
+
+    count = 0;
+    rusql_exec(&mut db, "SELECT * FROM a INNER JOIN b ON NumA = NumB;", |_, _| {
+        count += 1;
+    });
+    assert_eq!(count, 2);
+}
+
+#[test]
+fn test_select_join_using() {
+    let mut db = Rusql::new();
+    let expected = vec![vec![LiteralValue::Integer(1), LiteralValue::Text("x".to_string()), LiteralValue::Integer(10)],
+                        vec![LiteralValue::Integer(2), LiteralValue::Text("y".to_string()), LiteralValue::Integer(20)]];
+    let mut results: Vec<Vec<LiteralValue>> = Vec::new();
+
+    let sql_str = "CREATE TABLE a(Id INTEGER, Tag TEXT); \
+                   CREATE TABLE b(Id INTEGER, Tag TEXT, Num INTEGER); \
+                   INSERT INTO a VALUES(1, \"x\"), (2, \"y\"), (3, \"z\"); \
+                   INSERT INTO b VALUES(1, \"x\", 10), (2, \"y\", 20), (3, \"w\", 30); \
+                   SELECT * FROM a JOIN b USING (Id, Tag);";
+
+    let results_table = rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row.clone());
+    }).unwrap();
+    let names: Vec<String> = results_table.header.iter().map(|def| def.name.clone()).collect();
+
+    assert_eq!(names, vec!["Id".to_string(), "Tag".to_string(), "Num".to_string()]);
+    assert_eq!(results, expected);
+}
+
+#[test]
+fn test_select_join_using_missing_column() {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE a(Id INTEGER, Tag TEXT); \
+                   CREATE TABLE b(Id INTEGER); \
+                   INSERT INTO a VALUES(1, \"x\"); \
+                   INSERT INTO b VALUES(1), (2);";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    assert_eq!(select_rows(&mut db, "SELECT * FROM a JOIN b USING (Tag);"), Vec::<Vec<isize>>::new());
+    assert_eq!(select_rows(&mut db, "SELECT * FROM a JOIN b USING (Num);"), Vec::<Vec<isize>>::new());
+}
+
+#[test]
+fn test_select_outer_join_using_coalesces_columns() {
+    let mut db = init_db_for_outer_joins();
+    let expected = vec![vec![LiteralValue::Integer(2), LiteralValue::Integer(2), LiteralValue::Integer(2)],
+                        vec![LiteralValue::Integer(3), LiteralValue::Integer(3), LiteralValue::Integer(3)],
+                        vec![LiteralValue::Integer(4), LiteralValue::Null, LiteralValue::Integer(4)]];
+    let mut results: Vec<Vec<LiteralValue>> = Vec::new();
+
+    let sql_str = "CREATE TABLE c(NumA INTEGER); \
+                   INSERT INTO c VALUES(2), (3), (4); \
+                   SELECT NumA, a.NumA, c.NumA FROM a RIGHT JOIN c USING (NumA);";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row.clone());
+    });
+    assert_eq!(results, expected);
+
+    results.clear();
+    rusql_exec(&mut db, "SELECT * FROM a NATURAL FULL JOIN c;", |row, _| {
+        results.push(row.clone());
+    });
+    assert_eq!(results, vec![vec![LiteralValue::Integer(1)], vec![LiteralValue::Integer(2)],
+                             vec![LiteralValue::Integer(3)], vec![LiteralValue::Integer(4)]]);
+}
+
+#[test]
+fn test_select_from_subquery() {
+    let mut db = init_db_and_insert_into_table();
+    let mut results: Vec<Vec<LiteralValue>> = Vec::new();
+
+    let sql_str = "SELECT s.Num, Name FROM (SELECT Id + 10 AS Num, Name FROM Foo WHERE Id > 2) AS s WHERE Num < 14;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row.clone());
+    });
+
+    assert_eq!(results, vec![vec![LiteralValue::Integer(13), LiteralValue::Text("Bar3".to_string())]]);
+}
+
+#[test]
+fn test_select_join_with_subquery() {
+    let mut db = init_db_and_insert_into_table();
+    let expected = vec![vec![LiteralValue::Text("Bar1".to_string()), LiteralValue::Integer(2)],
+                        vec![LiteralValue::Text("Bar2".to_string()), LiteralValue::Integer(1)]];
+    let mut results: Vec<Vec<LiteralValue>> = Vec::new();
+
+    let sql_str = "CREATE TABLE Bar(FooId INTEGER); \
+                   INSERT INTO Bar VALUES(1), (2), (1); \
+                   SELECT Name, c.Total FROM Foo \
+                   JOIN (SELECT FooId, COUNT(*) AS Total FROM Bar GROUP BY FooId) c ON Foo.Id = c.FooId;";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push(row.clone());
+    });
+
+    assert_eq!(results, expected);
+}
+
+fn init_db_with_subquery_tables() -> Rusql {
+    let mut db = init_db_and_insert_into_table();
+    let sql_str = "CREATE TABLE Bar(FooId INTEGER); \
+                   INSERT INTO Bar VALUES(1), (3), (3);";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    db
+}
+
+#[test]
+fn test_select_in_subquery() {
+    let mut db = init_db_with_subquery_tables();
+    let mut results: Vec<isize> = Vec::new();
+
+    rusql_exec(&mut db, "SELECT Id FROM Foo WHERE Id IN (SELECT FooId FROM Bar);", |row, _| {
+        results.push(row[0].to_int());
+    });
+    rusql_exec(&mut db, "SELECT Id FROM Foo WHERE Id NOT IN (SELECT FooId FROM Bar) AND Id > 2;", |row, _| {
+        results.push(row[0].to_int());
+    });
+
+    assert_eq!(results, vec![1, 3, 4]);
+}
+
+#[test]
+fn test_select_in_subquery_with_nulls() {
+    let mut db = Rusql::new();
+    let mut results: Vec<LiteralValue> = Vec::new();
+
+    let sql_str = "CREATE TABLE Baz(Num INTEGER, Other INTEGER); \
+                   INSERT INTO Baz VALUES(1, 1); \
+                   INSERT INTO Baz(Other) VALUES(2); \
+                   SELECT 1 IN (SELECT Num FROM Baz), 2 IN (SELECT Num FROM Baz), 5 NOT IN (SELECT Num FROM Baz);";
+
+    rusql_exec(&mut db, sql_str, |row, _| {
+        results.push_all(row.as_slice());
+    });
+
+    assert_eq!(results, vec![LiteralValue::Boolean(true), LiteralValue::Null, LiteralValue::Null]);
+}
+
+#[test]
+fn test_select_exists_correlated() {
+    let mut db = init_db_with_subquery_tables();
+    let mut results: Vec<isize> = Vec::new();
+
+    rusql_exec(&mut db, "SELECT Id FROM Foo WHERE EXISTS (SELECT * FROM Bar WHERE Bar.FooId = Foo.Id);", |row, _| {
+        results.push(row[0].to_int());
+    });
+    rusql_exec(&mut db, "SELECT Id FROM Foo WHERE NOT EXISTS (SELECT * FROM Bar WHERE FooId = Id);", |row, _| {
+        results.push(row[0].to_int());
+    });
+
+    assert_eq!(results, vec![1, 3, 2, 4]);
+}
+
+#[test]
+fn test_select_subquery_with_several_columns() {
+    let mut db = init_db_with_subquery_tables();
+    let mut results: Vec<LiteralValue> = Vec::new();
+
+    rusql_exec(&mut db, "SELECT Id FROM Foo WHERE Id IN (SELECT FooId, FooId FROM Bar);", |row, _| {
+        results.push_all(row.as_slice());
+    });
+    rusql_exec(&mut db, "SELECT (SELECT Id, Name FROM Foo);", |row, _| {
+        results.push_all(row.as_slice());
+    });
+    rusql_exec(&mut db, "SELECT (SELECT * FROM Foo);", |row, _| {
+        results.push_all(row.as_slice());
+    });
+
+    assert_eq!(results, vec![LiteralValue::Null]);
+}
+
+#[test]
+fn test_select_scalar_subquery() {
+    let mut db = init_db_with_subquery_tables();
+    let expected = vec![vec![LiteralValue::Integer(1), LiteralValue::Integer(1)],
+                        vec![LiteralValue::Integer(2), LiteralValue::Integer(0)],
+                        vec![LiteralValue::Integer(3), LiteralValue::Integer(2)],
+                        vec![LiteralValue::Integer(4), LiteralValue::Integer(0)]];
+    let mut results: Vec<Vec<LiteralValue>> = Vec::new();
+
+    rusql_exec(&mut db, "SELECT Id, (SELECT COUNT(*) FROM Bar WHERE FooId = Id) FROM Foo;", |row, _| {
+        results.push(row.clone());
+    });
+    assert_eq!(results, expected);
+
+    results.clear();
+    rusql_exec(&mut db, "SELECT Name FROM Foo WHERE Id = (SELECT MAX(FooId) FROM Bar);", |row, _| {
+        results.push(row.clone());
+    });
+    assert_eq!(results, vec![vec![LiteralValue::Text("Bar3".to_string())]]);
+
+    results.clear();
+    rusql_exec(&mut db, "SELECT (SELECT FooId FROM Bar WHERE FooId > 5);", |row, _| {
+        results.push(row.clone());
+    });
+    assert_eq!(results, vec![vec![LiteralValue::Null]]);
+}
+
+fn init_db_for_compound_selects() -> Rusql {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE a(Num INTEGER); \
+                   CREATE TABLE b(Num INTEGER); \
+                   INSERT INTO a VALUES(1), (2), (2), (3); \
+                   INSERT INTO b VALUES(3), (4), (4);";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    db
+}
+
+fn select_column(db: &mut Rusql, sql_str: &str) -> Vec<isize> {
+    let mut results: Vec<isize> = Vec::new();
+
+    rusql_exec(db, sql_str, |row, _| {
+        results.push(row[0].to_int());
+    });
+
+    results
+}
+
+#[test]
+fn test_select_union() {
+    let mut db = init_db_for_compound_selects();
+
+    assert_eq!(select_column(&mut db, "SELECT Num FROM a UNION SELECT Num FROM b;"), vec![1, 2, 3, 4]);
+    assert_eq!(select_column(&mut db, "SELECT Num FROM a UNION ALL SELECT Num FROM b;"), vec![1, 2, 2, 3, 3, 4, 4]);
+}
+
+#[test]
+fn test_select_intersect_except() {
+    let mut db = init_db_for_compound_selects();
+
+    assert_eq!(select_column(&mut db, "SELECT Num FROM a INTERSECT SELECT Num FROM b;"), vec![3]);
+    assert_eq!(select_column(&mut db, "SELECT Num FROM a EXCEPT SELECT Num FROM b;"), vec![1, 2]);
+    // Compound operators are applied left to right.
+    assert_eq!(select_column(&mut db, "SELECT Num FROM a UNION ALL SELECT Num FROM b EXCEPT SELECT 4;"),
+               vec![1, 2, 3]);
+}
+
+#[test]
+fn test_select_compound_order_by_limit() {
+    let mut db = init_db_for_compound_selects();
+
+    let sql_str = "SELECT Num AS n FROM a UNION SELECT Num FROM b ORDER BY n DESC LIMIT 2 OFFSET 1;";
+
+    assert_eq!(select_column(&mut db, sql_str), vec![3, 2]);
+}
+
+#[test]
+fn test_select_compound_column_count_mismatch() {
+    let mut db = init_db_for_compound_selects();
+
+    let empty: Vec<isize> = Vec::new();
+
+    assert_eq!(select_column(&mut db, "SELECT Num FROM a UNION SELECT Num, Num FROM b;"), empty);
+}
+
+#[test]
+fn test_with_clause() {
+    let mut db = init_db_and_insert_into_table();
+
+    let sql_str = "WITH Big(Num, Nick) AS (SELECT Id, Name FROM Foo WHERE Id > 2), \
+                   Bigger AS (SELECT Num FROM Big WHERE Num > 3) \
+                   SELECT Big.Num FROM Big JOIN Bigger ON Big.Num = Bigger.Num;";
+
+    assert_eq!(select_column(&mut db, sql_str), vec![4]);
+    // The common table expressions only last for their statement.
+    assert!(!db.map.contains_key("Big"));
+}
+
+#[test]
+fn test_with_clause_before_delete() {
+    let mut db = init_db_and_insert_into_table();
+
+    rusql_exec(&mut db, "WITH Old AS (SELECT Id FROM Foo WHERE Id < 3) DELETE FROM Foo WHERE Id IN (SELECT Id FROM Old);",
+               |_,_| {});
+
+    assert_eq!(select_column(&mut db, "SELECT Id FROM Foo;"), vec![3, 4]);
+}
+
+#[test]
+fn test_with_clause_naming_modified_table() {
+    let mut db = init_db_and_insert_into_table();
+
+    rusql_exec(&mut db, "WITH Foo AS (SELECT Id FROM Foo WHERE Id < 3) DELETE FROM Foo;", |_,_| {});
+    rusql_exec(&mut db, "WITH Foo AS (SELECT 5) INSERT INTO Foo(Id) SELECT * FROM Foo;", |_,_| {});
+
+    assert_eq!(select_column(&mut db, "SELECT Id FROM Foo;"), vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn test_with_recursive() {
+    let mut db = Rusql::new();
+
+    let sql_str = "WITH RECURSIVE Counter(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM Counter WHERE n < 5) \
+                   SELECT n FROM Counter;";
+
+    assert_eq!(select_column(&mut db, sql_str), vec![1, 2, 3, 4, 5]);
+
+    let sql_str = "WITH RECURSIVE Counter(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM Counter LIMIT 3) \
+                   SELECT n FROM Counter;";
+
+    assert_eq!(select_column(&mut db, sql_str), vec![1, 2, 3]);
+}
+
+#[test]
+fn test_with_recursive_hierarchy() {
+    let mut db = Rusql::new();
+
+    let sql_str = "CREATE TABLE Emp(Id INTEGER PRIMARY KEY, Name TEXT, ManagerId INTEGER); \
+                   INSERT INTO Emp(Id, Name) VALUES(1, \"Alice\"); \
+                   INSERT INTO Emp VALUES(2, \"Bob\", 1), (3, \"Carol\", 1), (4, \"Dave\", 3), (5, \"Eve\", 2), (6, \"Zed\", 7); \
+                   WITH RECURSIVE Reports(Id) AS \
+                   (SELECT Id FROM Emp WHERE Name = \"Carol\" \
+                    UNION SELECT Emp.Id FROM Emp JOIN Reports ON Emp.ManagerId = Reports.Id) \
+                   SELECT Id FROM Reports;";
+
+    assert_eq!(select_column(&mut db, sql_str), vec![3, 4]);
+}
+
+fn init_db_for_window_functions() -> Rusql {
+    let mut db = Rusql::new();
+    let sql_str = "CREATE TABLE Sales(Region TEXT, Day INTEGER, Amount INTEGER); \
+                   INSERT INTO Sales VALUES(\"N\", 1, 10), (\"N\", 2, 20), (\"N\", 3, 20), (\"S\", 1, 5), (\"S\", 2, 15);";
+    rusql_exec(&mut db, sql_str, |_,_| {});
+
+    db
+}
+
+fn select_rows(db: &mut Rusql, sql_str: &str) -> Vec<Vec<isize>> {
+    let mut results: Vec<Vec<isize>> = Vec::new();
+
+    rusql_exec(db, sql_str, |row, _| {
+        results.push(row.iter().map(|column| column.to_int()).collect());
+    });
+
+    results
+}
+
+#[test]
+fn test_select_window_ranking() {
+    let mut db = init_db_for_window_functions();
+
+    let sql_str = "SELECT Amount, ROW_NUMBER() OVER (ORDER BY Amount DESC), RANK() OVER (ORDER BY Amount DESC), \
+                   DENSE_RANK() OVER (ORDER BY Amount DESC) FROM Sales;";
+
+    assert_eq!(select_rows(&mut db, sql_str),
+               vec![vec![10, 4, 4, 3], vec![20, 1, 1, 1], vec![20, 2, 1, 1], vec![5, 5, 5, 4], vec![15, 3, 3, 2]]);
+}
+
+#[test]
+fn test_select_window_partition_by() {
+    let mut db = init_db_for_window_functions();
+
+    let sql_str = "SELECT Amount, SUM(Amount) OVER (PARTITION BY Region ORDER BY Day), \
+                   COUNT(*) OVER (PARTITION BY Region) FROM Sales;";
+
+    assert_eq!(select_rows(&mut db, sql_str),
+               vec![vec![10, 10, 3], vec![20, 30, 3], vec![20, 50, 3], vec![5, 5, 2], vec![15, 20, 2]]);
+}
+
+#[test]
+fn test_select_window_frames() {
+    let mut db = init_db_for_window_functions();
+
+    let sql_str = "SELECT Day, SUM(Amount) OVER (ORDER BY Day ROWS BETWEEN 1 PRECEDING AND 1 FOLLOWING) \
+                   FROM Sales WHERE Region = \"N\";";
+
+    assert_eq!(select_rows(&mut db, sql_str), vec![vec![1, 30], vec![2, 50], vec![3, 40]]);
+
+    // The default frame, like a RANGE frame ending at the current row, takes in
+    // the row's peers.
+    let sql_str = "SELECT Day, SUM(Amount) OVER (ORDER BY Day), \
+                   SUM(Amount) OVER (ORDER BY Day RANGE BETWEEN CURRENT ROW AND 1 FOLLOWING) FROM Sales;";
+
+    assert_eq!(select_rows(&mut db, sql_str),
+               vec![vec![1, 15, 50], vec![2, 50, 55], vec![3, 70, 20], vec![1, 15, 50], vec![2, 50, 55]]);
+}
+
+#[test]
+fn test_select_window_in_aggregate_query() {
+    let mut db = init_db_for_window_functions();
+
+    let sql_str = "SELECT Region, SUM(Amount), RANK() OVER (ORDER BY SUM(Amount)) FROM Sales GROUP BY Region;";
+
+    assert_eq!(select_rows(&mut db, sql_str), Vec::<Vec<isize>>::new());
+}
+
+#[test]
+fn test_select_named_window() {
+    let mut db = init_db_for_window_functions();
+
+    let sql_str = "SELECT Day, LAG(Amount, 1, -1) OVER w, LEAD(Amount, 1, 0) OVER w, NTILE(2) OVER w, \
+                   FIRST_VALUE(Amount) OVER w, LAST_VALUE(Amount) OVER w, \
+                   LAST_VALUE(Amount) OVER (w ROWS BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING) \
+                   FROM Sales WHERE Region = \"N\" WINDOW w AS (ORDER BY Day);";
+
+    assert_eq!(select_rows(&mut db, sql_str),
+               vec![vec![1, -1, 20, 1, 10, 10, 20], vec![2, 10, 20, 1, 10, 20, 20], vec![3, 20, 0, 2, 10, 20, 20]]);
+}
+
+#[test]
+fn test_select_undefined_window() {
+    let mut db = init_db_for_window_functions();
+
+    let sql_str = "SELECT Day, RANK() OVER w FROM Sales; \
+                   SELECT Day FROM Sales WINDOW w AS (v ORDER BY Day), v AS (PARTITION BY Region); \
+                   SELECT Day FROM Sales ORDER BY SUM(Amount) OVER (w ROWS UNBOUNDED PRECEDING);";
+
+    assert_eq!(select_rows(&mut db, sql_str), Vec::<Vec<isize>>::new());
+}
+
+#[test]
+fn test_select_case() {
+    let mut db = init_db_and_insert_into_table();
+    let expected = vec![LiteralValue::Text("Small".to_string()), LiteralValue::Text("Small".to_string()),
+                        LiteralValue::Text("Bar3".to_string()), LiteralValue::Text("Bar4".to_string())];
+    let mut results: Vec<LiteralValue> = Vec::new();
+
+    rusql_exec(&mut db, "SELECT CASE WHEN Id > 2 THEN Name ELSE \"Small\" END FROM Foo;", |row, _| {
+        results.push(row[0].clone());
+    });
+
+    assert_eq!(results, expected);
+
+    let sql_str = "SELECT SUM(CASE WHEN Id > 2 THEN 1 ELSE 0 END), CASE WHEN COUNT(*) > 3 THEN 10 ELSE 20 END FROM Foo;";
+
+    assert_eq!(select_rows(&mut db, sql_str), vec![vec![2, 10]]);
+}
+
+#[test]
+fn test_select_where_predicates() {
+    let mut db = init_db_and_insert_into_table();
+
+    let sql_str = "SELECT Id FROM Foo WHERE Name LIKE 'bar%' AND Id BETWEEN 2 AND 3 OR Id IN (4, 5);";
+
+    assert_eq!(select_column(&mut db, sql_str), vec![2, 3, 4]);
+}
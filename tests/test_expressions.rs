@@ -182,3 +182,142 @@ fn test_bit_neg() {
 fn test_mult_div_associativity() {
     test_expect_ints("SELECT 9/3*3;", vec![9]);
 }
+
+#[test]
+fn test_case() {
+    test("SELECT CASE 2 WHEN 1 THEN \"one\" WHEN 2 THEN \"two\" ELSE \"many\" END;",
+         vec![LiteralValue::Text("two".to_string())]);
+    test("SELECT CASE 3 WHEN 1 THEN \"one\" END;", vec![LiteralValue::Null]);
+    test_expect_ints("SELECT CASE WHEN 1 > 2 THEN 1 WHEN 2 > 1 THEN 2 ELSE 3 END;", vec![2]);
+}
+
+#[test]
+fn test_case_is_lazy() {
+    // Evaluating the ELSE branch would divide by zero.
+    test_expect_ints("SELECT CASE WHEN 1 = 1 THEN 1 ELSE 1 / 0 END;", vec![1]);
+}
+
+#[test]
+fn test_operator_precedence() {
+    test_expect_ints("SELECT 1 + 2 < 5, 2 * (3 + 4), 10 - 2 - 3, 1 + 2 * 3;", vec![1, 14, 5, 7]);
+}
+
+#[test]
+fn test_between() {
+    test_expect_ints("SELECT 3 BETWEEN 1 AND 5, 3 NOT BETWEEN 1 AND 5, 3 BETWEEN 1 + 3 AND 5, 2 BETWEEN 1 AND 3 AND 0;",
+                     vec![1, 0, 0, 0]);
+}
+
+#[test]
+fn test_in_list() {
+    test_expect_ints("SELECT 2 IN (1, 2, 3), 4 IN (1, 2, 3), 4 NOT IN (1, 2, 3), 1 + 1 IN (2);", vec![1, 0, 1, 1]);
+}
+
+#[test]
+fn test_like() {
+    test_expect_ints("SELECT \"Banana\" LIKE \"b%n_\", \"Banana\" LIKE \"%x%\", \"Banana\" NOT LIKE \"ban%\";",
+                     vec![1, 0, 0]);
+    test_expect_ints("SELECT \"10%\" LIKE \"10!%\" ESCAPE \"!\", \"100\" LIKE \"10!%\" ESCAPE \"!\";", vec![1, 0]);
+    test_expect_ints("SELECT \"abcbd\" LIKE \"%b%%d\", \"abc\" LIKE \"a%%\", \"abc\" LIKE \"%c_\";", vec![1, 1, 0]);
+}
+
+#[test]
+fn test_like_with_many_wildcards() {
+    let value: String = std::iter::repeat('a').take(200).collect();
+    let sql_str = format!("SELECT \"{}\" LIKE \"%a%a%a%a%a%a%a%a%b\", \"{}b\" LIKE \"%a%a%a%a%a%a%a%a%b\";",
+                          value, value);
+
+    test_expect_ints(sql_str.as_slice(), vec![0, 1]);
+}
+
+#[test]
+fn test_like_escape_must_be_one_character() {
+    let mut db = Rusql::new();
+
+    assert!(rusql_exec(&mut db, "SELECT \"a\" LIKE \"a\" ESCAPE \"!!\";", |_,_| {}).is_none());
+}
+
+#[test]
+fn test_glob() {
+    test_expect_ints("SELECT \"Banana\" GLOB \"B*\", \"Banana\" GLOB \"b*\", \"Banana\" GLOB \"[A-C]?n[^x]*\";",
+                     vec![1, 0, 1]);
+}
+
+#[test]
+fn test_string_functions() {
+    test("SELECT length(\"hello\"), lower(\"AbC\"), upper(\"AbC\"), instr(\"banana\", \"nan\"), instr(\"banana\", \"x\");",
+         vec![LiteralValue::Integer(5), LiteralValue::Text("abc".to_string()), LiteralValue::Text("ABC".to_string()),
+              LiteralValue::Integer(3), LiteralValue::Integer(0)]);
+    test("SELECT substr(\"Hello\", 2, 3), substr(\"Hello\", -3), substr(\"Hello\", 3, -2), replace(\"banana\", \"an\", \"AN\");",
+         vec![LiteralValue::Text("ell".to_string()), LiteralValue::Text("llo".to_string()),
+              LiteralValue::Text("He".to_string()), LiteralValue::Text("bANANa".to_string())]);
+    test("SELECT trim(\"  x  \"), ltrim(\"xxaxx\", \"x\"), rtrim(\"xxaxx\", \"x\");",
+         vec![LiteralValue::Text("x".to_string()), LiteralValue::Text("axx".to_string()),
+              LiteralValue::Text("xxa".to_string())]);
+}
+
+#[test]
+fn test_numeric_functions() {
+    test("SELECT abs(-5), max(1, 5, 3), min(4, 2, 8), round(3);",
+         vec![LiteralValue::Integer(5), LiteralValue::Integer(5), LiteralValue::Integer(2), LiteralValue::Real(3.0)]);
+}
+
+#[test]
+fn test_abs_overflow() {
+    let mut db = Rusql::new();
+    let mut rows = 0us;
+    let sql_str = format!("SELECT abs(0 - {} - 1);", std::isize::MAX);
+
+    // The smallest integer has no positive counterpart.
+    let results = rusql_exec(&mut db, sql_str.as_slice(), |_,_| { rows += 1; });
+
+    assert!(results.is_none());
+    assert_eq!(rows, 0);
+}
+
+#[test]
+fn test_null_functions() {
+    test("SELECT coalesce(nullif(1, 1), nullif(2, 2), 3), ifnull(nullif(1, 1), 4), nullif(1, 2), max(1, nullif(1, 1));",
+         vec![LiteralValue::Integer(3), LiteralValue::Integer(4), LiteralValue::Integer(1), LiteralValue::Null]);
+    // Evaluating the second argument would divide by zero.
+    test_expect_ints("SELECT coalesce(1, 1 / 0);", vec![1]);
+}
+
+#[test]
+fn test_quote_and_hex() {
+    test("SELECT quote(\"it's\"), quote(1), quote(nullif(1, 1)), hex(\"Az\");",
+         vec![LiteralValue::Text("'it''s'".to_string()), LiteralValue::Text("1".to_string()),
+              LiteralValue::Text("NULL".to_string()), LiteralValue::Text("417A".to_string())]);
+}
+
+#[test]
+fn test_printf() {
+    test("SELECT printf(\"%-4s|%04d|%+d|%x|%.2s|%5.2f%%\", \"ab\", 42, 7, 255, \"xyz\", 2), format(\"%s and %q\", \"a\", \"it's\");",
+         vec![LiteralValue::Text("ab  |0042|+7|ff|xy| 2.00%".to_string()),
+              LiteralValue::Text("a and it''s".to_string())]);
+}
+
+#[test]
+fn test_function_arity() {
+    let mut db = Rusql::new();
+
+    assert!(rusql_exec(&mut db, "SELECT abs(1, 2);", |_,_| {}).is_none());
+    assert!(rusql_exec(&mut db, "SELECT substr(\"a\");", |_,_| {}).is_none());
+    assert!(rusql_exec(&mut db, "SELECT no_such_function(1);", |_,_| {}).is_none());
+}
+
+#[test]
+fn test_cast() {
+    test("SELECT CAST(\" 12.7abc\" AS INTEGER), CAST(\"-3e2x\" AS REAL), CAST(\"abc\" AS INTEGER), CAST(42 AS TEXT);",
+         vec![LiteralValue::Integer(12), LiteralValue::Real(-300.0), LiteralValue::Integer(0),
+              LiteralValue::Text("42".to_string())]);
+    test("SELECT CAST(\"3.0\" AS NUMERIC), CAST(\"3.5\" AS NUMERIC), CAST(CAST(\"-7.9\" AS REAL) AS INTEGER);",
+         vec![LiteralValue::Integer(3), LiteralValue::Real(3.5), LiteralValue::Integer(-7)]);
+    test("SELECT CAST(nullif(1, 1) AS INTEGER), CAST(1 = 1 AS TEXT);",
+         vec![LiteralValue::Null, LiteralValue::Text("1".to_string())]);
+}
+
+#[test]
+fn test_text_truth_value() {
+    test_expect_ints("SELECT CASE WHEN \"2x\" THEN 1 ELSE 0 END, CASE WHEN \"x\" THEN 1 ELSE 0 END;", vec![1, 0]);
+}